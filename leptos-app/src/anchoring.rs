@@ -0,0 +1,289 @@
+//! Re-anchoring of stored selectors against the current article text.
+//!
+//! Offsets are codepoints into the article's plain text. The strategies run
+//! in order: `TextPosition` (verified against the quote when one is stored),
+//! exact `TextQuote` search disambiguated by prefix/suffix, then approximate
+//! matching of the quote for articles that changed since the annotation was
+//! written.
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::annotation::Annotation;
+use crate::offset::TextOffset;
+use crate::selector::Selector;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnchorError {
+    /// The stored `selectors` JSON could not be parsed.
+    InvalidSelectors,
+    /// Neither a usable position nor a quote was stored.
+    NoSelectors,
+    /// The quote no longer appears in the article, even approximately.
+    QuoteNotFound,
+}
+
+impl fmt::Display for AnchorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnchorError::InvalidSelectors => f.write_str("stored selectors are unreadable"),
+            AnchorError::NoSelectors => f.write_str("no usable selector"),
+            AnchorError::QuoteNotFound => f.write_str("quoted text not found in the article"),
+        }
+    }
+}
+
+struct Quote<'a> {
+    exact: &'a str,
+    prefix: Option<&'a str>,
+    suffix: Option<&'a str>,
+}
+
+// Top-level selectors followed by anything they are `refinedBy`.
+fn with_refinements(selectors: &[Selector]) -> impl Iterator<Item = &Selector> {
+    selectors.iter().flat_map(|s| std::iter::successors(Some(s), |s| s.refined_by()))
+}
+
+fn find_quote(selectors: &[Selector]) -> Option<Quote<'_>> {
+    with_refinements(selectors).find_map(|s| match s {
+        Selector::TextQuote { exact, prefix, suffix, .. } if !exact.is_empty() => Some(Quote {
+            exact,
+            prefix: prefix.as_deref(),
+            suffix: suffix.as_deref(),
+        }),
+        _ => None,
+    })
+}
+
+// Stored positions may use any unit; anchoring works in codepoints.
+fn find_position(text: &str, selectors: &[Selector]) -> Option<(usize, usize)> {
+    with_refinements(selectors).find_map(|s| match s {
+        Selector::TextPosition { start, end, unit, .. } if start < end => {
+            let unit = unit.unwrap_or_default();
+            let start = TextOffset::new(*start, unit).to_codepoint(text)?;
+            let end = TextOffset::new(*end, unit).to_codepoint(text)?;
+            Some((start, end))
+        }
+        _ => None,
+    })
+}
+
+/// Locate the annotated range in `text`, returning codepoint offsets.
+pub fn anchor(text: &str, selectors: &[Selector]) -> Result<(usize, usize), AnchorError> {
+    let chars: Vec<char> = text.chars().collect();
+    let quote = find_quote(selectors);
+    let position = find_position(text, selectors);
+
+    if let Some((start, end)) = position {
+        match &quote {
+            None => return Ok((start, end)),
+            Some(q) if chars[start..end].iter().copied().eq(q.exact.chars()) => return Ok((start, end)),
+            Some(_) => {}
+        }
+    }
+
+    let Some(quote) = quote else {
+        return Err(AnchorError::NoSelectors);
+    };
+    let hint = position.map(|(start, _)| start);
+    let exact: Vec<char> = quote.exact.chars().collect();
+
+    if let Some(start) = best_exact_match(&chars, &exact, &quote, hint) {
+        return Ok((start, start + exact.len()));
+    }
+    approximate_match(&chars, &exact, hint).ok_or(AnchorError::QuoteNotFound)
+}
+
+fn common_suffix_len(a: &[char], b: &[char]) -> usize {
+    a.iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count()
+}
+
+fn common_prefix_len(a: &[char], b: &[char]) -> usize {
+    a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count()
+}
+
+fn distance_to(hint: Option<usize>, start: usize) -> usize {
+    hint.map(|h| h.abs_diff(start)).unwrap_or(0)
+}
+
+// Every exact occurrence is scored by how much of the stored prefix/suffix
+// context still surrounds it; ties go to the occurrence nearest the old position.
+fn best_exact_match(text: &[char], exact: &[char], quote: &Quote<'_>, hint: Option<usize>) -> Option<usize> {
+    if exact.len() > text.len() {
+        return None;
+    }
+    let prefix: Vec<char> = quote.prefix.unwrap_or_default().chars().collect();
+    let suffix: Vec<char> = quote.suffix.unwrap_or_default().chars().collect();
+    (0..=text.len() - exact.len())
+        .filter(|&i| text[i..i + exact.len()] == *exact)
+        .max_by_key(|&i| {
+            let before = &text[i.saturating_sub(prefix.len())..i];
+            let after = &text[i + exact.len()..(i + exact.len() + suffix.len()).min(text.len())];
+            let context = common_suffix_len(before, &prefix) + common_prefix_len(after, &suffix);
+            (context, std::cmp::Reverse(distance_to(hint, i)))
+        })
+}
+
+// Sellers' algorithm: edit distance of `pattern` against every substring of
+// `text`, tracking where each candidate match starts.
+fn approximate_match(text: &[char], pattern: &[char], hint: Option<usize>) -> Option<(usize, usize)> {
+    let m = pattern.len();
+    let max_errors = m / 4;
+    if m == 0 || max_errors == 0 {
+        return None;
+    }
+    let mut cost: Vec<usize> = (0..=m).collect();
+    let mut start: Vec<usize> = vec![0; m + 1];
+    let mut best: Option<(usize, usize, usize)> = None;
+    for (j, &c) in text.iter().enumerate() {
+        let (mut diag_cost, mut diag_start) = (0, j);
+        cost[0] = 0;
+        start[0] = j + 1;
+        for i in 1..=m {
+            let (up_cost, up_start) = (cost[i], start[i]);
+            let candidates = [
+                (diag_cost + usize::from(pattern[i - 1] != c), diag_start),
+                (up_cost + 1, up_start),
+                (cost[i - 1] + 1, start[i - 1]),
+            ];
+            let (c_cost, c_start) = candidates.into_iter().min_by_key(|&(cost, _)| cost).unwrap_or_default();
+            diag_cost = up_cost;
+            diag_start = up_start;
+            cost[i] = c_cost;
+            start[i] = c_start;
+        }
+        if cost[m] <= max_errors {
+            let candidate = (cost[m], start[m], j + 1);
+            let better = match best {
+                None => true,
+                Some((c, s, _)) => (cost[m], distance_to(hint, start[m])) < (c, distance_to(hint, s)),
+            };
+            if better {
+                best = Some(candidate);
+            }
+        }
+    }
+    best.map(|(_, s, e)| (s, e))
+}
+
+/// Outcome of one [`Anchored::pass`].
+#[derive(Default)]
+pub struct Pass {
+    /// Newly marked roots and where each starts.
+    pub starts: Vec<(i64, usize)>,
+    pub failed: Vec<(i64, AnchorError)>,
+}
+
+/// Roots already highlighted in the article or found orphaned, so that
+/// each is anchored once however often the store changes. The article does
+/// not change while the page is open, so an orphan is not retried.
+#[derive(Default)]
+pub struct Anchored {
+    marked: HashSet<i64>,
+    orphaned: HashSet<i64>,
+}
+
+impl Anchored {
+    /// Take over marks made elsewhere for `id`, such as the composer's.
+    pub fn claim(&mut self, id: i64) {
+        self.marked.insert(id);
+    }
+
+    /// Mark the roots in `items` not seen before. `mark` highlights one and
+    /// returns where it starts, or why it could not.
+    pub fn pass(&mut self, items: &[Annotation], mut mark: impl FnMut(&Annotation) -> Result<usize, AnchorError>) -> Pass {
+        let mut pass = Pass::default();
+        for a in items.iter().filter(|a| a.parent_id.is_none()) {
+            if self.marked.contains(&a.id) || self.orphaned.contains(&a.id) {
+                continue;
+            }
+            match mark(a) {
+                Ok(start) => {
+                    self.marked.insert(a.id);
+                    pass.starts.push((a.id, start));
+                }
+                Err(e) => {
+                    self.orphaned.insert(a.id);
+                    pass.failed.push((a.id, e));
+                }
+            }
+        }
+        pass
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offset::TextUnit;
+
+    fn quote(exact: &str, prefix: &str, suffix: &str) -> Selector {
+        Selector::TextQuote {
+            exact: exact.into(),
+            prefix: Some(prefix.into()),
+            suffix: Some(suffix.into()),
+            refined_by: None,
+        }
+    }
+
+    fn position(start: usize, end: usize) -> Selector {
+        Selector::TextPosition { start, end, unit: Some(TextUnit::Codepoint), refined_by: None }
+    }
+
+    #[test]
+    fn position_is_used_when_it_matches_the_quote() {
+        let text = "猫が好き。猫が好き。";
+        let sel = [quote("猫が好き", "。", "。"), position(5, 9)];
+        assert_eq!(anchor(text, &sel), Ok((5, 9)));
+    }
+
+    #[test]
+    fn quote_context_picks_the_right_occurrence_after_edits() {
+        let text = "intro. the cat sat. later the cat ran.";
+        let sel = [quote("the cat", "later ", " ran"), position(0, 7)];
+        assert_eq!(anchor(text, &sel), Ok((26, 33)));
+    }
+
+    #[test]
+    fn utf16_positions_are_converted() {
+        let text = "😀猫が好き";
+        let sel = [
+            quote("猫", "", ""),
+            Selector::TextPosition { start: 2, end: 3, unit: Some(TextUnit::Utf16), refined_by: None },
+        ];
+        assert_eq!(anchor(text, &sel), Ok((1, 2)));
+    }
+
+    #[test]
+    fn approximate_match_survives_small_edits() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let sel = [quote("quick brwn fox jumps", "", ""), position(100, 120)];
+        assert_eq!(anchor(text, &sel), Ok((4, 25)));
+    }
+
+    #[test]
+    fn roots_are_anchored_once() {
+        use crate::annotation::fixture;
+
+        let items = [fixture(1, None, "1"), fixture(2, None, "2"), fixture(3, Some(1), "3")];
+        let mut anchored = Anchored::default();
+        let mut tried = vec![];
+        let pass = anchored.pass(&items, |a| {
+            tried.push(a.id);
+            if a.id == 1 { Ok(4) } else { Err(AnchorError::QuoteNotFound) }
+        });
+        assert_eq!((pass.starts, pass.failed), (vec![(1, 4)], vec![(2, AnchorError::QuoteNotFound)]));
+        let pass = anchored.pass(&items, |a| {
+            tried.push(a.id);
+            Ok(0)
+        });
+        assert!(pass.starts.is_empty() && pass.failed.is_empty());
+        assert_eq!(tried, vec![1, 2]);
+    }
+
+    #[test]
+    fn missing_quote_is_reported() {
+        let sel = [quote("entirely different words", "", "")];
+        assert_eq!(anchor("nothing to see here", &sel), Err(AnchorError::QuoteNotFound));
+        assert_eq!(anchor("text", &[]), Err(AnchorError::NoSelectors));
+    }
+}
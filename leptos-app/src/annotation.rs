@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+
+use crate::kind::AnnotationKind;
+use crate::selector::Envelope;
+
+/// A row from `/api/annotations/list`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Annotation {
+    pub id: i64,
+    pub display_name: Option<String>,
+    pub body_html: String,
+    pub parent_id: Option<i64>,
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub quote: Option<String>,
+    #[serde(default)]
+    pub selectors: Option<String>,
+    #[serde(default)]
+    pub kind: AnnotationKind,
+    /// Moderation state; only our own annotations are seen as `pending`.
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(skip)]
+    pub sync: Sync,
+}
+
+/// Where a client-side copy stands relative to the server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Sync {
+    #[default]
+    Saved,
+    /// Inserted optimistically; the request has not completed yet.
+    Sending,
+    /// Waiting in the offline outbox.
+    Queued,
+    /// Parked in the outbox after the server refused it.
+    Failed,
+}
+
+/// Payload of `/api/annotations/create`, or `/reply` when `parent_id` is set.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NewAnnotation {
+    pub post_slug: String,
+    pub display_name: Option<String>,
+    pub body_html: String,
+    pub selectors: Envelope,
+    pub quote: String,
+    pub kind: AnnotationKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<i64>,
+    /// Kept for every retry of this annotation, including outbox flushes.
+    pub idempotency_key: String,
+}
+
+impl NewAnnotation {
+    /// The entry shown in the sidebar until the server assigns an id.
+    pub fn provisional(&self, created_at: String) -> Annotation {
+        Annotation {
+            id: 0,
+            display_name: self.display_name.clone(),
+            body_html: self.body_html.clone(),
+            parent_id: self.parent_id,
+            created_at: Some(created_at),
+            quote: Some(self.quote.clone()),
+            selectors: serde_json::to_string(&self.selectors).ok(),
+            kind: self.kind,
+            state: None,
+            sync: Sync::Sending,
+        }
+    }
+}
+
+impl Annotation {
+    /// Stored but held back from the public list for moderation.
+    pub fn is_held(&self) -> bool {
+        self.state.as_deref() == Some("pending")
+    }
+
+    pub fn author(&self) -> String {
+        self.display_name.clone().filter(|n| !n.is_empty()).unwrap_or_else(|| "Anonymous".into())
+    }
+}
+
+/// A saved row with empty content, for tests.
+#[cfg(test)]
+pub(crate) fn fixture(id: i64, parent_id: Option<i64>, created_at: &str) -> Annotation {
+    Annotation {
+        id,
+        display_name: None,
+        body_html: String::new(),
+        parent_id,
+        created_at: Some(created_at.into()),
+        quote: None,
+        selectors: None,
+        kind: Default::default(),
+        state: None,
+        sync: Sync::Saved,
+    }
+}
@@ -0,0 +1,320 @@
+//! Typed client for the server API.
+//!
+//! Every request goes through [`Client::request`], which applies the base
+//! URL, a timeout and the client's cancellation, and turns failed
+//! responses into [`ApiError`]s. Requests still running when the client is
+//! cancelled, e.g. because the component that owns it unmounted, are
+//! aborted and resolve to [`ApiError::Cancelled`].
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{window, AbortController, RequestInit, Response};
+
+use crate::annotation::{Annotation, NewAnnotation};
+use crate::botcheck::BotCheck;
+use crate::error::{ApiError, DEFAULT_RETRY_AFTER};
+use crate::retry;
+
+const BASE_META: &str = "meta[name=\"api-base\"]";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest page `/api/annotations/list` returns.
+pub const LIST_LIMIT_MAX: u32 = 200;
+
+/// Response of `/api/annotations/create` and `/reply`.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Created {
+    pub id: i64,
+    #[serde(default)]
+    pub state: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Submit<'a> {
+    #[serde(flatten)]
+    new: &'a NewAnnotation,
+    turnstile_token: String,
+}
+
+/// Payload of `/api/annotations/report`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ReportRequest {
+    pub annotation_id: i64,
+    pub reason: String,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ReportAccepted {
+    pub accepted: bool,
+}
+
+/// Moderation decision for `/api/mod/update`.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModState {
+    Published,
+    Rejected,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct ModUpdate {
+    pub id: i64,
+    pub state: ModState,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ModUpdated {
+    pub ok: bool,
+}
+
+/// Payload of `/api/posts/upsert`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct PostUpsert {
+    pub slug: String,
+    pub html: String,
+    pub plain_text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published_at: Option<String>,
+}
+
+/// `created` is set when the slug was new, `updated` otherwise.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct PostUpserted {
+    pub id: i64,
+    #[serde(default)]
+    pub created: bool,
+    #[serde(default)]
+    pub updated: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Client {
+    base: Rc<str>,
+    timeout: Duration,
+    inflight: Rc<RefCell<Vec<AbortController>>>,
+    cancelled: Rc<Cell<bool>>,
+}
+
+impl Client {
+    /// `base` is prefixed to every path; empty means the page's origin.
+    pub fn new(base: &str, timeout: Duration) -> Client {
+        Client {
+            base: base.trim_end_matches('/').into(),
+            timeout,
+            inflight: Rc::default(),
+            cancelled: Rc::default(),
+        }
+    }
+
+    /// A client for the base URL in `<meta name="api-base" content="…">`,
+    /// or the page's origin without one.
+    pub fn from_page() -> Client {
+        let base = window()
+            .and_then(|w| w.document())
+            .and_then(|d| d.query_selector(BASE_META).ok().flatten())
+            .and_then(|meta| meta.get_attribute("content"))
+            .unwrap_or_default();
+        Client::new(base.trim(), DEFAULT_TIMEOUT)
+    }
+
+    /// Abort every running request and refuse new ones.
+    pub fn cancel_all(&self) {
+        self.cancelled.set(true);
+        for controller in self.inflight.borrow_mut().drain(..) {
+            controller.abort();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.get()
+    }
+
+    /// Published annotations on `slug` created after `after`, oldest first.
+    pub async fn list(&self, slug: &str, after: Option<&str>, limit: Option<u32>) -> Result<Vec<Annotation>, ApiError> {
+        let mut path = format!("/api/annotations/list?slug={}", encode(slug));
+        if let Some(after) = after {
+            path.push_str(&format!("&after={}", encode(after)));
+        }
+        if let Some(limit) = limit {
+            path.push_str(&format!("&limit={}", limit.clamp(1, LIST_LIMIT_MAX)));
+        }
+        self.request("GET", &path, None).await
+    }
+
+    pub async fn create(&self, new: &NewAnnotation, turnstile_token: String) -> Result<Created, ApiError> {
+        self.post("/api/annotations/create", &Submit { new, turnstile_token }).await
+    }
+
+    pub async fn reply(&self, new: &NewAnnotation, turnstile_token: String) -> Result<Created, ApiError> {
+        self.post("/api/annotations/reply", &Submit { new, turnstile_token }).await
+    }
+
+    /// Post `new` as an annotation or, with `parent_id`, a reply, retrying
+    /// network and server errors with backoff under the same idempotency
+    /// key. A `409 conflict` means an earlier attempt was stored, so its id
+    /// is returned as success; `rate_limited` waits out `Retry-After`
+    /// before the next attempt.
+    pub async fn submit(&self, bot: &BotCheck, new: &NewAnnotation) -> Result<Created, ApiError> {
+        let mut attempt = 0;
+        // a rejected token is retried once with a fresh one
+        let mut bot_retried = false;
+        loop {
+            let sent = match bot.token().await {
+                Ok(token) if new.parent_id.is_some() => self.reply(new, token).await,
+                Ok(token) => self.create(new, token).await,
+                Err(e) => return Err(ApiError::BotCheck(e)),
+            };
+            let error = match sent {
+                Ok(created) => return Ok(created),
+                Err(ApiError::Conflict { id: Some(id) }) => return Ok(Created { id, state: None }),
+                Err(ApiError::BotSuspected) if !bot_retried => {
+                    bot_retried = true;
+                    continue;
+                }
+                Err(error) => error,
+            };
+            let delay = match &error {
+                ApiError::RateLimited { retry_after } => retry_after.unwrap_or(DEFAULT_RETRY_AFTER),
+                e if e.is_transient() => retry::backoff(attempt),
+                _ => return Err(error),
+            };
+            if attempt + 1 >= retry::MAX_ATTEMPTS || delay > retry::MAX_WAIT {
+                return Err(error);
+            }
+            retry::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    pub async fn report(&self, body: &ReportRequest) -> Result<ReportAccepted, ApiError> {
+        self.post("/api/annotations/report", body).await
+    }
+}
+
+// Moderation and publishing endpoints, for tools built on this crate; the
+// reader UI does not call them.
+impl Client {
+    /// Annotations held for moderation, oldest first.
+    pub async fn mod_list(&self) -> Result<Vec<Annotation>, ApiError> {
+        self.request("GET", "/api/mod/list", None).await
+    }
+
+    pub async fn mod_update(&self, body: &ModUpdate) -> Result<ModUpdated, ApiError> {
+        self.post("/api/mod/update", body).await
+    }
+
+    pub async fn posts_upsert(&self, body: &PostUpsert) -> Result<PostUpserted, ApiError> {
+        self.post("/api/posts/upsert", body).await
+    }
+}
+
+impl Client {
+    async fn post<T: DeserializeOwned>(&self, path: &str, body: &impl Serialize) -> Result<T, ApiError> {
+        let body = serde_json::to_string(body).map_err(|e| ApiError::InvalidInput(Some(e.to_string())))?;
+        self.request("POST", path, Some(body)).await
+    }
+
+    async fn request<T: DeserializeOwned>(&self, method: &str, path: &str, body: Option<String>) -> Result<T, ApiError> {
+        if self.cancelled.get() {
+            return Err(ApiError::Cancelled);
+        }
+        let win = window().ok_or_else(|| ApiError::Network("no window".into()))?;
+        let controller = AbortController::new().map_err(js_error)?;
+        let init = RequestInit::new();
+        init.set_method(method);
+        init.set_signal(Some(&controller.signal()));
+        let headers = web_sys::Headers::new().map_err(js_error)?;
+        headers.set("Accept", "application/json").map_err(js_error)?;
+        if let Some(body) = &body {
+            headers.set("Content-Type", "application/json").map_err(js_error)?;
+            init.set_body(&JsValue::from_str(body));
+        }
+        init.set_headers(&headers);
+
+        let timed_out = Rc::new(Cell::new(false));
+        let on_timeout = Closure::once({
+            let controller = controller.clone();
+            let timed_out = timed_out.clone();
+            move || {
+                timed_out.set(true);
+                controller.abort();
+            }
+        });
+        let timer = win
+            .set_timeout_with_callback_and_timeout_and_arguments_0(
+                on_timeout.as_ref().unchecked_ref(),
+                self.timeout.as_millis() as i32,
+            )
+            .map_err(js_error)?;
+        self.inflight.borrow_mut().push(controller.clone());
+
+        let url = format!("{}{}", self.base, path);
+        let result = fetch_text(&win, &url, &init).await;
+
+        win.clear_timeout_with_handle(timer);
+        drop(on_timeout);
+        self.inflight.borrow_mut().retain(|c| c != &controller);
+
+        let (status, text, retry_after) = match result {
+            Ok(done) => done,
+            Err(_) if self.cancelled.get() => return Err(ApiError::Cancelled),
+            Err(_) if timed_out.get() => return Err(ApiError::Network("timed out".into())),
+            Err(e) => return Err(e),
+        };
+        if !(200..300).contains(&status) {
+            return Err(ApiError::from_response(status, &text, retry_after.as_deref()));
+        }
+        serde_json::from_str(&text).map_err(|_| ApiError::Http(status))
+    }
+}
+
+// Status, body text and `Retry-After` of a completed request.
+async fn fetch_text(win: &web_sys::Window, url: &str, init: &RequestInit) -> Result<(u16, String, Option<String>), ApiError> {
+    let resp: Response = JsFuture::from(win.fetch_with_str_and_init(url, init))
+        .await
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(js_error)?;
+    let text = JsFuture::from(resp.text().map_err(js_error)?).await.map_err(js_error)?;
+    let retry_after = resp.headers().get("Retry-After").ok().flatten();
+    Ok((resp.status(), text.as_string().unwrap_or_default(), retry_after))
+}
+
+fn js_error(e: JsValue) -> ApiError {
+    ApiError::Network(format!("{:?}", e))
+}
+
+fn encode(value: &str) -> String {
+    js_sys::encode_uri_component(value).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_request_bodies() {
+        let update = ModUpdate { id: 3, state: ModState::Rejected };
+        assert_eq!(serde_json::to_string(&update).unwrap(), r#"{"id":3,"state":"rejected"}"#);
+        let upsert = PostUpsert {
+            slug: "hello".into(),
+            html: "<p>hi</p>".into(),
+            plain_text: "hi".into(),
+            revision: None,
+            content_hash: None,
+            published_at: None,
+        };
+        assert_eq!(serde_json::to_string(&upsert).unwrap(), r#"{"slug":"hello","html":"<p>hi</p>","plain_text":"hi"}"#);
+        let created: PostUpserted = serde_json::from_str(r#"{"created":true,"id":9}"#).unwrap();
+        assert!(created.created && !created.updated);
+    }
+}
@@ -1,40 +1,40 @@
 use leptos::*;
-use serde::{Deserialize, Serialize};
 use wasm_bindgen::JsCast;
 use wasm_bindgen::closure::Closure;
-use wasm_bindgen_futures::JsFuture;
-use web_sys::{window, Event, MouseEvent};
+use web_sys::{window, Event, KeyboardEvent};
 use std::rc::Rc;
+use std::time::Duration;
 use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use crate::anchoring::{self, AnchorError, Anchored};
+use crate::botcheck::{self, BotCheck};
+use crate::api::{self, Client, ReportRequest};
+use crate::annotation::{Annotation, NewAnnotation, Sync};
+use crate::dom_text::{self, article_root};
+use crate::draft::{self, Draft};
+use crate::error::{ApiError, Field, Lang, Status, DISPLAY_NAME_MAX};
+use crate::fragment::{self, FragmentTarget, TextDirective};
+use crate::highlight;
+use crate::kind::AnnotationKind;
+use crate::live;
+use crate::markdown::{self, MODERATION_URL_THRESHOLD};
+use crate::outbox::{self, Queued};
+use crate::paging;
+use crate::offset::TextUnit;
+use crate::report::{self, Reason};
+use crate::retry;
+use crate::sanitize;
+use crate::selection::{self, collapse_selection, current_selection, selection_rect};
+use crate::search::Query;
+use crate::selector::{Envelope, Selector};
+use crate::sort::{self, SortMode};
+use crate::store::{self, AnnotationStore};
+use crate::thread::{self, card_dom_id, ThreadCtx, Threads};
 
-#[derive(Serialize, Clone)]
-#[serde(tag = "type")]
-enum Selector {
-    #[serde(rename = "TextQuoteSelector")]
-    TextQuote {
-        exact: String,
-        prefix: Option<String>,
-        suffix: Option<String>,
-    },
-    #[serde(rename = "TextPositionSelector")]
-    TextPosition {
-        start: usize,
-        end: usize,
-        unit: &'static str,
-    },
-}
-
-#[derive(Serialize, Clone)]
-struct Target {
-    source: String,
-    selector: Vec<Selector>,
-}
-
-#[derive(Serialize, Clone)]
-struct Envelope {
-    r#type: &'static str,
-    target: Target,
-}
+// Highlight id for the reader's unsent selection
+const OWN_DRAFT_ID: &str = "own";
+// Highlight id for the passage a `#:~:text=` link points at
+const FRAGMENT_ID: &str = "fragment";
 
 fn path_slug() -> Option<String> {
     let loc = window()?.location();
@@ -48,108 +48,61 @@ fn path_slug() -> Option<String> {
 }
 
 fn article_plain_text() -> Option<String> {
-    let doc = window()?.document()?;
-    let article = doc.query_selector("article").ok().flatten()?;
-    Some(article.text_content().unwrap_or_default())
+    let article = article_root()?;
+    Some(dom_text::plain_text(&article))
 }
 
-fn current_selection() -> Option<(String, usize, usize)> {
-    let sel = window()?.get_selection().ok().flatten()?;
-    if sel.range_count() == 0 {
-        return None;
-    }
-    let js = sel.to_string();
-    let text: String = js.into();
-    if text.is_empty() {
-        return None;
+fn scroll_to_card(id: i64) {
+    if let Some(card) = window().and_then(|w| w.document()).and_then(|d| d.get_element_by_id(&card_dom_id(id))) {
+        let opts = web_sys::ScrollIntoViewOptions::new();
+        opts.set_behavior(web_sys::ScrollBehavior::Smooth);
+        opts.set_block(web_sys::ScrollLogicalPosition::Nearest);
+        card.scroll_into_view_with_scroll_into_view_options(&opts);
     }
-    // naive position via plain_text find
-    let plain = article_plain_text()?;
-    let start = plain.find(&text)?;
-    let end = start + text.chars().count();
-    Some((text, start, end))
 }
 
-fn selection_rect() -> Option<(f64, f64)> {
-    let win = window()?;
-    let sel = win.get_selection().ok().flatten()?;
-    if sel.range_count() == 0 { return None; }
-    let range = sel.get_range_at(0).ok()?;
-    if let Some(rects) = range.get_client_rects() {
-        if let Ok(val) = js_sys::Reflect::get(rects.as_ref(), &wasm_bindgen::JsValue::from_str("0")) {
-            if let Ok(rect) = val.dyn_into::<web_sys::DomRectReadOnly>() {
-                return Some((rect.right() + 6.0, rect.bottom() + 6.0));
-            }
-        }
+// Browsers with native text fragment support strip `:~:` directives from
+// `location.hash`; the navigation entry still carries the full URL.
+fn current_hash() -> String {
+    let Some(win) = window() else { return String::new() };
+    let hash = win.location().hash().unwrap_or_default();
+    if hash.contains(":~:") {
+        return hash;
+    }
+    let entries = win.performance().map(|p| p.get_entries_by_type("navigation"));
+    let nav_url = entries
+        .and_then(|list| list.get(0).dyn_into::<web_sys::PerformanceEntry>().ok())
+        .map(|entry| entry.name())
+        .unwrap_or_default();
+    match nav_url.split_once('#') {
+        Some((_, frag)) if frag.contains(":~:") => format!("#{}", frag),
+        _ => hash,
     }
-    None
 }
 
-fn collapse_selection() {
-    if let Some(sel) = window().and_then(|w| w.get_selection().ok().flatten()) {
-        let _ = sel.collapse_to_end();
-    }
+fn copy_link(hash: &str) -> bool {
+    let Some(win) = window() else { return false };
+    let Ok(loc) = win.location().href() else { return false };
+    let base = loc.split('#').next().unwrap_or("");
+    let url = format!("{}{}", base, hash);
+    let _ = win.navigator().clipboard().write_text(&url);
+    true
 }
 
-fn wrap_selection_with_mark(class_name: &str) {
-    if let Some(win) = window() {
-        if let Some(sel) = win.get_selection().ok().flatten() {
-            if sel.range_count() > 0 {
-                if let Ok(range) = sel.get_range_at(0) {
-                    if let Some(doc) = win.document() {
-                        if let Ok(mark) = doc.create_element("mark") {
-                            mark.set_class_name(class_name);
-                            let _ = range.surround_contents(&mark);
-                        }
-                    }
-                }
+fn wrap_selection_with_mark(id: &str, class_name: &str) {
+    if let Some(sel) = window().and_then(|w| w.get_selection().ok().flatten()) {
+        if sel.range_count() > 0 {
+            if let Ok(range) = sel.get_range_at(0) {
+                highlight::highlight_range(&range, id, class_name);
             }
         }
     }
 }
 
-async fn post_annotation(
-    slug: &str,
-    env: Envelope,
-    quote: &str,
-    display_name: Option<String>,
-    body_html: String,
-) -> Result<(), String> {
-    #[derive(Serialize)]
-    struct Body<'a> {
-        post_slug: &'a str,
-        display_name: Option<&'a str>,
-        body_html: String,
-        selectors: Envelope,
-        quote: &'a str,
-        turnstile_token: &'a str,
-        idempotency_key: String,
-    }
-    let body = Body {
-        post_slug: slug,
-        display_name: display_name.as_deref(),
-        body_html,
-        selectors: env,
-        quote,
-        turnstile_token: "test-anything",
-        idempotency_key: format!("id-{}", js_sys::Date::now()),
-    };
-    let req_init = web_sys::RequestInit::new();
-    req_init.set_method("POST");
-    let body_str = serde_json::to_string(&body).map_err(|e| e.to_string())?;
-    req_init.set_body(&wasm_bindgen::JsValue::from_str(&body_str));
-    let req = web_sys::Request::new_with_str_and_init("/api/annotations/create", &req_init)
-        .map_err(|e| format!("{:?}", e))?;
-    req.headers().set("Content-Type", "application/json").ok();
-    req.headers().set("Accept", "application/json").ok();
-    let resp_val = JsFuture::from(window().unwrap().fetch_with_request(&req))
-        .await
-        .map_err(|e| format!("{:?}", e))?;
-    let resp: web_sys::Response = resp_val.dyn_into().unwrap();
-    if !resp.ok() {
-        return Err(format!("status {}", resp.status()));
-    }
-    Ok(())
+// `created_at` in the format SQLite's `datetime('now')` stores, so
+// provisional entries sort among server rows.
+fn now_timestamp() -> String {
+    String::from(js_sys::Date::new_0().to_iso_string()).replace('T', " ").chars().take(19).collect()
 }
 
 fn build_envelope(slug: &str, exact: &str, start: usize, end: usize) -> Envelope {
@@ -160,59 +113,71 @@ fn build_envelope(slug: &str, exact: &str, start: usize, end: usize) -> Envelope
     } else { None };
     let total = plain.chars().count();
     let suffix = if end < total { Some(plain.chars().skip(end).take(20).collect()) } else { None };
-    Envelope {
-        r#type: "Annotation",
-        target: Target {
-            source: format!("/posts/{}", slug),
-            selector: vec![
-                Selector::TextQuote {
-                    exact: exact.to_string(),
-                    prefix,
-                    suffix,
-                },
-                Selector::TextPosition {
-                    start,
-                    end,
-                    unit: "codepoint",
-                },
-            ],
-        },
-    }
+    Envelope::new(
+        format!("/posts/{}", slug),
+        vec![
+            Selector::TextQuote {
+                exact: exact.to_string(),
+                prefix,
+                suffix,
+                refined_by: None,
+            },
+            Selector::TextPosition {
+                start,
+                end,
+                unit: Some(TextUnit::Codepoint),
+                refined_by: None,
+            },
+        ],
+    )
 }
 
 #[component]
 pub fn App() -> impl IntoView {
-    #[derive(Serialize, Deserialize, Clone)]
-    struct Annotation {
-        id: i64,
-        display_name: Option<String>,
-        body_html: String,
-        parent_id: Option<i64>,
-        created_at: Option<String>,
-    }
-
     let slug: Rc<Option<String>> = Rc::new(path_slug());
     let (status, set_status) = create_signal(String::new());
 
-    // Load annotations
+    // API client; requests still running when the app unmounts are aborted
+    let client = Client::from_page();
+    on_cleanup({
+        let client = client.clone();
+        move || client.cancel_all()
+    });
+    let api = store_value(client);
+
+    // Load the first page of annotations; later pages follow as the
+    // sidebar scrolls
     let slug_for_res = slug.clone();
-    let annotations = create_resource(move || slug_for_res.clone(), |slug_rc: Rc<Option<String>>| async move {
-        let mut out: Vec<Annotation> = vec![];
-        if let Some(slug) = (*slug_rc).clone() {
-            let url = format!("/api/annotations/list?slug={}", slug);
-            if let Ok(resp_val) = JsFuture::from(window().unwrap().fetch_with_str(&url)).await {
-                if let Ok(resp) = resp_val.dyn_into::<web_sys::Response>() {
-                    if resp.ok() {
-                        if let Ok(text_js) = JsFuture::from(resp.text().unwrap()).await {
-                            if let Some(text) = text_js.as_string() {
-                                out = serde_json::from_str(&text).unwrap_or_default();
-                            }
-                        }
-                    }
-                }
+    let annotations = create_local_resource(move || slug_for_res.clone(), move |slug_rc: Rc<Option<String>>| async move {
+        match (*slug_rc).clone() {
+            Some(slug) => api.get_value().list(&slug, None, Some(paging::PAGE_SIZE)).await,
+            None => Ok(vec![]),
+        }
+    });
+    let cursor = store_value::<Option<String>>(None);
+    // Newest `created_at` fetched so far, where live updates resume
+    let latest = store_value::<Option<String>>(None);
+    let (more, set_more) = create_signal(false);
+
+    let lang = Lang::detect();
+
+    // Bot check for posting, chosen by the page's Turnstile configuration
+    let bot = store_value(BotCheck::from_page());
+
+    // Fetched pages are merged into the store, which also holds our own
+    // annotations while they are being sent
+    let store = AnnotationStore::new();
+    create_effect(move |_| {
+        match annotations.get() {
+            Some(Ok(items)) => {
+                set_more.set(paging::has_more(items.len()));
+                cursor.set_value(paging::next_cursor(&items));
+                latest.update_value(|l| live::advance(l, &items));
+                store.merge(items);
             }
+            Some(Err(ApiError::Cancelled)) | None => {}
+            Some(Err(e)) => set_status.set(e.message(lang)),
         }
-        out
     });
 
     // Compose state
@@ -221,10 +186,25 @@ pub fn App() -> impl IntoView {
     let (compose_env, set_compose_env) = create_signal::<Option<Envelope>>(None);
     let (input_name, set_input_name) = create_signal(String::new());
     let (input_body, set_input_body) = create_signal(String::new());
+    let (input_kind, set_input_kind) = create_signal(AnnotationKind::default());
+    // Composer field the last error was about
+    let (field_error, set_field_error) = create_signal::<Option<Field>>(None);
+    let field_class = move |field: Field, base: &'static str| {
+        move || if field_error.get() == Some(field) { format!("{} field-error", base) } else { base.to_string() }
+    };
 
     // Sidebar overlay state (mobile)
     let (sidebar_open, set_sidebar_open) = create_signal(false);
 
+    // Annotation focused from either its highlight or its sidebar card
+    let (focused, set_focused) = create_signal::<Option<i64>>(None);
+    create_effect(move |_| {
+        let id = focused.get().map(|id| id.to_string());
+        if let Some(article) = article_root() {
+            highlight::set_focused(&article, id.as_deref());
+        }
+    });
+
     // Selection popover state
     #[derive(Clone)]
     struct PopState { show: bool, x: f64, y: f64, preview: String }
@@ -232,7 +212,6 @@ pub fn App() -> impl IntoView {
 
     // Comments (dev.to-like) state
     let (comments_open, set_comments_open) = create_signal(false);
-    let (comment_draft_created, set_comment_draft_created) = create_signal(false);
     let (comment_input, set_comment_input) = create_signal(String::new());
 
     // Toast state
@@ -242,7 +221,7 @@ pub fn App() -> impl IntoView {
         set_toast_msg.set(msg.to_string());
         set_toast_show.set(true);
         if let Some(w) = window() {
-            let setter = set_toast_show.clone();
+            let setter = set_toast_show;
             let cb = Closure::once_into_js(Box::new(move || {
                 setter.set(false);
             }) as Box<dyn FnOnce()>);
@@ -251,88 +230,784 @@ pub fn App() -> impl IntoView {
         }
     };
 
-    async fn create_comment_draft(slug: Option<String>) -> Result<(), String> {
-        if slug.is_none() { return Ok(()); }
-        let url = "/api/comments/open";
-        let init = web_sys::RequestInit::new();
-        init.set_method("POST");
-        #[derive(Serialize)]
-        struct Body<'a> { post_slug: &'a str }
-        let body = Body { post_slug: slug.as_ref().unwrap() };
-        let s = serde_json::to_string(&body).map_err(|e| e.to_string())?;
-        init.set_body(&wasm_bindgen::JsValue::from_str(&s));
-        let req = web_sys::Request::new_with_str_and_init(url, &init).map_err(|e| format!("{:?}", e))?;
-        req.headers().set("Content-Type", "application/json").ok();
-        let resp = JsFuture::from(window().unwrap().fetch_with_request(&req)).await.map_err(|e| format!("{:?}", e))?;
-        let resp: web_sys::Response = resp.dyn_into().unwrap();
-        if !resp.ok() { return Err(format!("status {}", resp.status())); }
-        Ok(())
-    }
+    // Provide slug via memo for handlers
+    let slug_memo = create_memo(move |_| slug.clone());
+    let body_ref = create_node_ref::<html::Textarea>();
+    let hide_pop = move || set_pop.set(PopState { show: false, x: 0.0, y: 0.0, preview: String::new() });
+
+    // Open the composer for the current selection; shared by the popover,
+    // the keyboard shortcut and touch selection. The selection stays until
+    // the annotation is sent or cancelled, and opening again for a changed
+    // selection moves the pending highlight to it. Touch leaves the focus
+    // alone, as focusing the textarea would drop the selection handles.
+    let open_composer_at = move |focus: bool| {
+        let Some((exact, start, end)) = current_selection() else { return };
+        if let Some(slug) = (*slug_memo.get_untracked()).clone() {
+            set_compose_env.set(Some(build_envelope(&slug, &exact, start, end)));
+            set_compose_quote.set(exact);
+            set_status.set(String::new());
+            set_field_error.set(None);
+        }
+        if let Some(article) = article_root() { highlight::remove_highlight(&article, OWN_DRAFT_ID); }
+        wrap_selection_with_mark(OWN_DRAFT_ID, &format!("{} anno--own", input_kind.get_untracked().mark_class()));
+        hide_pop();
+        set_compose_open.set(true);
+        set_sidebar_open.set(true);
+        if focus {
+            set_timeout(move || {
+                if let Some(textarea) = body_ref.get_untracked() { let _ = textarea.focus(); }
+            }, std::time::Duration::ZERO);
+        }
+    };
+    let open_composer = move || open_composer_at(true);
+
+    // Keep the open composer's text in localStorage so a reload or
+    // navigation does not lose it
+    create_effect(move |_| {
+        let (body, name, kind) = (input_body.get(), input_name.get(), input_kind.get());
+        if !compose_open.get() { return; }
+        let (Some(slug), Some(env)) = ((*slug_memo.get_untracked()).clone(), compose_env.get()) else { return };
+        if body.trim().is_empty() {
+            draft::remove(&slug, &env);
+        } else {
+            draft::save(&slug, &Draft { env, quote: compose_quote.get_untracked(), name, body, kind, saved_at: js_sys::Date::now() });
+        }
+    });
+
+    // Every draft saved for this post, most recent first, with its current
+    // codepoint range or why it no longer anchors; those are shown with
+    // their saved quote so the text can still be copied out before
+    // discarding. Each stays on offer until restored or discarded.
+    let (draft_offers, set_draft_offers) = create_signal::<Vec<(Draft, Result<(usize, usize), AnchorError>)>>(vec![]);
+    let offer_drafts = move || {
+        let Some(slug) = (*slug_memo.get_untracked()).clone() else { return };
+        let plain = article_plain_text().unwrap_or_default();
+        let offers = draft::load_all(&slug).into_iter().map(|d| {
+            let anchored = anchoring::anchor(&plain, &d.env.target.selector);
+            (d, anchored)
+        });
+        set_draft_offers.set(offers.collect());
+    };
+    // Take the draft for `hash` off offer and out of storage.
+    let take_draft = move |hash: &str| {
+        let slug = (*slug_memo.get_untracked()).clone()?;
+        let i = draft_offers.with_untracked(|offers| offers.iter().position(|(d, _)| draft::selector_hash(&d.env) == hash))?;
+        let mut taken = None;
+        set_draft_offers.update(|offers| taken = Some(offers.remove(i)));
+        let (d, anchored) = taken?;
+        draft::remove(&slug, &d.env);
+        Some((slug, d, anchored))
+    };
+    let restore_draft = move |hash: String| {
+        let Some((slug, d, Ok((start, end)))) = take_draft(&hash) else { return };
+        let Some(article) = article_root() else { return };
+        // the passage may have moved; the draft is saved again under its new selectors
+        let exact: String = dom_text::plain_text(&article).chars().skip(start).take(end - start).collect();
+        set_compose_env.set(Some(build_envelope(&slug, &exact, start, end)));
+        set_compose_quote.set(exact);
+        set_input_name.set(d.name);
+        set_input_kind.set(d.kind);
+        set_input_body.set(d.body);
+        highlight::remove_highlight(&article, OWN_DRAFT_ID);
+        if let Some(range) = dom_text::range_from_offsets(&article, start, end) {
+            highlight::highlight_range(&range, OWN_DRAFT_ID, &format!("{} anno--own", d.kind.mark_class()));
+        }
+        set_status.set(String::new());
+        set_compose_open.set(true);
+        set_sidebar_open.set(true);
+        set_timeout(move || {
+            if let Some(textarea) = body_ref.get_untracked() { let _ = textarea.focus(); }
+        }, std::time::Duration::ZERO);
+    };
+    let discard_draft = move |hash: String| {
+        take_draft(&hash);
+    };
+    let drafts_checked = Rc::new(Cell::new(false));
+    create_effect(move |_| {
+        // offered after the first load so highlights are already in place
+        if annotations.get().is_none() || drafts_checked.get() { return; }
+        drafts_checked.set(true);
+        offer_drafts();
+    });
 
-    // Listen for selection in the article (mouseup) and show popover (run once)
+    // Track the selection in the article for mouse, keyboard and touch, and
+    // show the popover once it settles (run once)
     let once = Rc::new(Cell::new(false));
     let once2 = once.clone();
-    let slug_for_effect = slug.clone();
     create_effect(move |_| {
         if once2.get() { return; }
         once2.set(true);
         let doc = window().unwrap().document().unwrap();
-        let article = doc.query_selector("article").ok().flatten();
-        let slug_in_handler = slug_for_effect.clone();
-        let handler = Closure::wrap(Box::new(move |e: Event| {
-            let me: MouseEvent = e.dyn_ref::<MouseEvent>().unwrap().clone();
-            if let Some(target) = me.target() {
-                let el = target.dyn_into::<web_sys::Node>().ok();
-                if let Some(node) = el {
-                    if let Some(ref art) = article {
-                        if !art.contains(Some(&node)) { return; }
-                    }
-                    if let Some((exact, start, end)) = current_selection() {
-                        if let Some((x, y)) = selection_rect() {
-                            let preview = if exact.chars().count() > 80 { format!("{}…", exact.chars().take(80).collect::<String>()) } else { exact.clone() };
-                            set_pop.set(PopState { show: true, x, y, preview });
-                        }
-                        if let Some(slug) = (*slug_in_handler).clone() {
-                            let env = build_envelope(&slug, &exact, start, end);
-                            set_compose_env.set(Some(env));
-                            set_compose_quote.set(exact);
-                            set_status.set(String::new());
-                        }
+        selection::on_selection_settled(move || {
+            // while composing, the selection belongs to the composer
+            if compose_open.get_untracked() { return; }
+            match current_selection() {
+                Some((exact, _, _)) => {
+                    if let Some((x, y)) = selection_rect() {
+                        let preview = if exact.chars().count() > 80 { format!("{}…", exact.chars().take(80).collect::<String>()) } else { exact.clone() };
+                        set_pop.set(PopState { show: true, x, y, preview });
                     }
                 }
+                None => if pop.get_untracked().show { hide_pop() },
+            }
+        });
+        selection::on_touch_selection(move || open_composer_at(false));
+        let on_key = Closure::wrap(Box::new(move |e: Event| {
+            let Some(ke) = e.dyn_ref::<KeyboardEvent>() else { return };
+            if selection::is_compose_shortcut(ke) && current_selection().is_some() {
+                e.prevent_default();
+                open_composer();
+            } else if ke.key() == "Escape" && pop.get_untracked().show {
+                hide_pop();
             }
         }) as Box<dyn FnMut(_)>);
-        doc.add_event_listener_with_callback("mouseup", handler.as_ref().unchecked_ref())
+        doc.add_event_listener_with_callback("keydown", on_key.as_ref().unchecked_ref())
             .ok();
-        handler.forget();
+        on_key.forget();
+
+        // Highlights focus their sidebar card on hover and scroll to it on click
+        if let Some(art) = article_root() {
+            let mark_id = |e: &Event| -> Option<i64> {
+                let el = e.target()?.dyn_into::<web_sys::Element>().ok()?;
+                highlight::annotation_id_at(&el)?.parse().ok()
+            };
+            let on_over = Closure::wrap(Box::new(move |e: Event| {
+                if let Some(id) = mark_id(&e) {
+                    if focused.get_untracked() != Some(id) { set_focused.set(Some(id)); }
+                }
+            }) as Box<dyn FnMut(_)>);
+            let on_click = Closure::wrap(Box::new(move |e: Event| {
+                if let Some(id) = mark_id(&e) {
+                    set_focused.set(Some(id));
+                    set_sidebar_open.set(true);
+                    scroll_to_card(id);
+                }
+            }) as Box<dyn FnMut(_)>);
+            art.add_event_listener_with_callback("mouseover", on_over.as_ref().unchecked_ref()).ok();
+            art.add_event_listener_with_callback("click", on_click.as_ref().unchecked_ref()).ok();
+            on_over.forget();
+            on_click.forget();
+        }
+    });
+
+    // Re-anchor stored annotations and highlight them in the article;
+    // roots that no longer match the text are collected as orphans
+    let anchored = store_value(Anchored::default());
+    // Where each anchored root starts in the article text, for sorting
+    let (positions, set_positions) = create_signal(HashMap::<i64, usize>::new());
+    let (orphans, set_orphans) = create_signal::<Vec<(i64, AnchorError)>>(vec![]);
+    create_effect(move |_| {
+        if annotations.get().is_none() { return; }
+        let Some(article) = article_root() else { return };
+        let plain = dom_text::plain_text(&article);
+        let mark = |a: &Annotation| {
+            let (start, end) = a.selectors.as_deref()
+                .and_then(Envelope::from_json)
+                .ok_or(AnchorError::InvalidSelectors)
+                .and_then(|env| anchoring::anchor(&plain, &env.target.selector))?;
+            let range = dom_text::range_from_offsets(&article, start, end).ok_or(AnchorError::QuoteNotFound)?;
+            highlight::highlight_range(&range, &a.id.to_string(), &a.kind.mark_class());
+            Ok(start)
+        };
+        let Some(pass) = store.with(|items| anchored.try_update_value(|state| state.pass(items, mark))) else { return };
+        // one update per pass, and none without news; every update re-renders the sidebar
+        if !pass.starts.is_empty() { set_positions.update(|p| p.extend(pass.starts)); }
+        if !pass.failed.is_empty() { set_orphans.update(|o| o.extend(pass.failed)); }
+    });
+
+    // Scroll to and highlight the target of `#anno-<id>` or `#:~:text=` links,
+    // without relying on native text fragment support
+    let reveal_hash = move || {
+        let Some(article) = article_root() else { return };
+        match fragment::parse_hash(&current_hash()) {
+            Some(FragmentTarget::Annotation(id)) => {
+                set_focused.set(Some(id));
+                if !highlight::reveal(&article, &id.to_string()) {
+                    scroll_to_card(id);
+                }
+            }
+            Some(FragmentTarget::Text(directive)) => {
+                highlight::remove_highlight(&article, FRAGMENT_ID);
+                let plain = dom_text::plain_text(&article);
+                if let Some(range) = directive.resolve(&plain).and_then(|(s, e)| dom_text::range_from_offsets(&article, s, e)) {
+                    highlight::highlight_range(&range, FRAGMENT_ID, "anno anno--fragment");
+                    highlight::reveal(&article, FRAGMENT_ID);
+                }
+            }
+            None => {}
+        }
+    };
+    let hash_done = Rc::new(Cell::new(false));
+    create_effect(move |_| {
+        // wait for the first load so annotation highlights exist
+        if annotations.get().is_none() || hash_done.get() { return; }
+        hash_done.set(true);
+        reveal_hash();
+        let on_hash = Closure::wrap(Box::new(move |_: Event| reveal_hash()) as Box<dyn FnMut(_)>);
+        if let Some(w) = window() {
+            w.add_event_listener_with_callback("hashchange", on_hash.as_ref().unchecked_ref()).ok();
+        }
+        on_hash.forget();
     });
 
     // Handlers are inlined in the view to satisfy Fn trait requirements
 
-    // Render sidebar list
-    let list_view = move || {
-        annotations.get().map(|items| {
-            if items.is_empty() {
-                view! { <div class="item">No comments yet.</div> }.into_view()
-            } else {
-                // simple grouping: roots then replies
-                let mut roots: Vec<&Annotation> = items.iter().filter(|a| a.parent_id.is_none()).collect();
-                let replies: Vec<&Annotation> = items.iter().filter(|a| a.parent_id.is_some()).collect();
-                roots.sort_by_key(|a| a.created_at.clone());
-                let nodes = roots.into_iter().map(|r| {
-                    let rnode = view! { <div class="item root"><div class="meta">{r.display_name.clone().unwrap_or_else(|| "Anonymous".into())}</div><div inner_html={r.body_html.clone()}></div></div> };
-                    let child_nodes = replies.iter().filter(|c| c.parent_id == Some(r.id)).map(|c| {
-                        view! { <div class="item reply" style="margin-left:8px"><div class="meta">{c.display_name.clone().unwrap_or_else(|| "Anonymous".into())}</div><div inner_html={c.body_html.clone()}></div></div> }
-                    }).collect_view();
-                    view! { <>{rnode}{child_nodes}</> }
-                }).collect_view();
-                nodes.into_view()
+    // Own annotations that were sent or queued move from their provisional
+    // id to the one the server assigned. A live poll may have brought the
+    // saved row in already, highlighted and counted as new; then the
+    // provisional marks go and the row is no longer news.
+    let (unseen, set_unseen) = create_signal::<Vec<i64>>(vec![]);
+    let confirm_local = move |local_id: i64, created: api::Created| {
+        set_unseen.update(|u| u.retain(|id| *id != created.id));
+        if store.get_untracked(created.id).is_some() {
+            if let Some(article) = article_root() { highlight::remove_highlight(&article, &local_id.to_string()); }
+        } else {
+            anchored.update_value(|state| state.claim(created.id));
+            if let Some(article) = article_root() {
+                highlight::relabel(&article, &local_id.to_string(), &created.id.to_string());
             }
+        }
+        store.confirm(local_id, created.id, created.state);
+    };
+
+    // Offline outbox: queued annotations stay in the sidebar as "pending
+    // sync" and are sent in order once the connection is back, or by a
+    // retry timer that backs off while the server stays unreachable. The
+    // server refusing one parks it, so it cannot hold up the rest.
+    let queued_ids = store_value(HashMap::<String, i64>::new());
+    let queue_offline = move |new: NewAnnotation, local_id: i64| {
+        queued_ids.update_value(|ids| { ids.insert(new.idempotency_key.clone(), local_id); });
+        outbox::push(Queued { new, created_at: now_timestamp(), parked: false });
+        store.set_sync(local_id, Sync::Queued);
+        set_status.set(if outbox::is_online() { Status::Queued } else { Status::SavedOffline }.message(lang));
+    };
+    let queued_key = move |id: i64| queued_ids.with_value(|ids| ids.iter().find(|(_, v)| **v == id).map(|(k, _)| k.clone()));
+    let flushing = store_value(false);
+    let flush_failures = store_value(0u32);
+    let flush_outbox = move || {
+        if flushing.get_value() || !outbox::is_online() { return; }
+        flushing.set_value(true);
+        spawn_local(async move {
+            // until the queue is empty, so entries added meanwhile go too
+            'flush: loop {
+                let pending: Vec<Queued> = outbox::load().into_iter().filter(|q| !q.parked).collect();
+                if pending.is_empty() {
+                    flush_failures.set_value(0);
+                    break;
+                }
+                for queued in pending {
+                    let key = queued.new.idempotency_key.clone();
+                    let local_id = queued_ids.with_value(|ids| ids.get(&key).copied());
+                    if let Some(id) = local_id { store.set_sync(id, Sync::Sending); }
+                    match api.get_value().submit(&bot.get_value(), &queued.new).await {
+                        Ok(created) => {
+                            outbox::remove(&key);
+                            if let Some(id) = local_id { confirm_local(id, created); }
+                        }
+                        Err(ApiError::Cancelled) => break 'flush,
+                        Err(e) if e.is_outage() => {
+                            // keep this and everything after it, in order, for the retry timer
+                            if let Some(id) = local_id { store.set_sync(id, Sync::Queued); }
+                            if !matches!(e, ApiError::Network(_)) { show_toast(&e.message(lang)); }
+                            flush_failures.update_value(|n| *n += 1);
+                            break 'flush;
+                        }
+                        Err(e) => {
+                            // waiting will not fix this; the reader retries or discards it
+                            outbox::set_parked(&key, true);
+                            if let Some(id) = local_id { store.set_sync(id, Sync::Failed); }
+                            show_toast(&e.message(lang));
+                        }
+                    }
+                }
+            }
+            flushing.set_value(false);
+        });
+    };
+    let on_retry_send = Callback::new(move |id: i64| {
+        let Some(key) = queued_key(id) else { return };
+        outbox::set_parked(&key, false);
+        store.set_sync(id, Sync::Queued);
+        flush_outbox();
+    });
+    let on_discard = Callback::new(move |id: i64| {
+        let Some(key) = queued_key(id) else { return };
+        outbox::remove(&key);
+        queued_ids.update_value(|ids| { ids.remove(&key); });
+        store.discard(id);
+        if let Some(article) = article_root() { highlight::remove_highlight(&article, &id.to_string()); }
+    });
+    let outbox_loaded = Rc::new(Cell::new(false));
+    create_effect(move |_| {
+        // after the first load, so queued annotations anchor next to the rest
+        if annotations.get().is_none() || outbox_loaded.get() { return; }
+        outbox_loaded.set(true);
+        let slug = (*slug_memo.get_untracked()).clone();
+        for queued in outbox::load().into_iter().filter(|q| Some(&q.new.post_slug) == slug.as_ref()) {
+            let local_id = store.insert_provisional(queued.new.provisional(queued.created_at));
+            store.set_sync(local_id, if queued.parked { Sync::Failed } else { Sync::Queued });
+            queued_ids.update_value(|ids| { ids.insert(queued.new.idempotency_key, local_id); });
+        }
+        outbox::on_online(flush_outbox);
+        flush_outbox();
+        let client = api.get_value();
+        spawn_local(async move {
+            loop {
+                retry::sleep(live::interval(flush_failures.get_value())).await;
+                if client.is_cancelled() { break; }
+                if outbox::has_pending() { flush_outbox(); }
+            }
+        });
+    });
+
+    // Threaded replies
+    let collapsed = create_rw_signal(HashSet::<i64>::new());
+    let reply_to = create_rw_signal::<Option<i64>>(None);
+    let reply_body = create_rw_signal(String::new());
+    let reply_status = create_rw_signal(String::new());
+    let on_send_reply = Callback::new(move |parent_id: i64| {
+        let Some(slug) = (*slug_memo.get_untracked()).clone() else { return };
+        let Some(parent) = store.get_untracked(parent_id) else { return };
+        if store::is_provisional(parent_id) {
+            reply_status.set(Status::ParentUnsent.message(lang));
+            return;
+        }
+        // replies carry their thread's selectors so the server validator accepts them
+        let Some(env) = parent.selectors.as_deref().and_then(Envelope::from_json) else {
+            reply_status.set(Status::ParentUnanchored.message(lang));
+            return;
+        };
+        let source = reply_body.get_untracked();
+        if source.trim().is_empty() { return; }
+        let body = markdown::to_html(&source);
+        let name = input_name.get_untracked();
+        let idempotency_key = match retry::new_idempotency_key() {
+            Ok(key) => key,
+            Err(e) => {
+                reply_status.set(Status::NoIdempotencyKey(e).message(lang));
+                return;
+            }
+        };
+        let new = NewAnnotation {
+            post_slug: slug,
+            display_name: (!name.is_empty()).then_some(name),
+            body_html: body,
+            selectors: env,
+            quote: parent.quote.clone().unwrap_or_default(),
+            kind: AnnotationKind::Comment,
+            parent_id: Some(parent_id),
+            idempotency_key,
+        };
+        let local_id = store.insert_provisional(new.provisional(now_timestamp()));
+        reply_to.set(None);
+        reply_body.set(String::new());
+        reply_status.set(String::new());
+        collapsed.update(|c| { c.remove(&parent_id); });
+        // behind anything still queued, so nothing overtakes an older annotation
+        if !outbox::is_online() || outbox::has_pending() {
+            queue_offline(new, local_id);
+            flush_outbox();
+            return;
+        }
+        spawn_local(async move {
+            match api.get_value().submit(&bot.get_value(), &new).await {
+                Ok(created) => confirm_local(local_id, created),
+                Err(ApiError::Network(_)) => queue_offline(new, local_id),
+                Err(ApiError::Cancelled) => {}
+                Err(e) => {
+                    // put the reply back in its composer
+                    store.discard(local_id);
+                    reply_to.set(Some(parent_id));
+                    reply_body.set(source);
+                    reply_status.set(e.message(lang));
+                }
+            }
+        });
+    });
+    // Reporting: a modal dialog with predefined reasons and a note; the
+    // reported card is dimmed for this reader
+    let reported = create_rw_signal(report::load());
+    let report_ref = create_node_ref::<html::Dialog>();
+    let (report_target, set_report_target) = create_signal::<Option<i64>>(None);
+    let (report_reason, set_report_reason) = create_signal(Reason::default());
+    let (report_note, set_report_note) = create_signal(String::new());
+    let (report_status, set_report_status) = create_signal(String::new());
+    let (report_sending, set_report_sending) = create_signal(false);
+    let on_report = Callback::new(move |id: i64| {
+        set_report_target.set(Some(id));
+        set_report_reason.set(Reason::default());
+        set_report_note.set(String::new());
+        set_report_status.set(String::new());
+        if let Some(dialog) = report_ref.get_untracked() {
+            let _ = dialog.show_modal();
+        }
+    });
+    let close_report = move || {
+        if let Some(dialog) = report_ref.get_untracked() { dialog.close(); }
+    };
+    let submit_report = move || {
+        let Some(annotation_id) = report_target.get_untracked() else { return };
+        if report_sending.get_untracked() { return; }
+        let Some(reason) = report_reason.get_untracked().with_note(&report_note.get_untracked()) else {
+            set_report_status.set(Status::MissingReportNote.message(lang));
+            return;
+        };
+        set_report_sending.set(true);
+        set_report_status.set(String::new());
+        spawn_local(async move {
+            let sent = api.get_value().report(&ReportRequest { annotation_id, reason }).await;
+            set_report_sending.set(false);
+            match sent {
+                Ok(_) => {
+                    reported.update(|r| { r.insert(annotation_id); });
+                    reported.with_untracked(report::save);
+                    close_report();
+                    show_toast("Thanks — the annotation was reported");
+                }
+                Err(ApiError::Cancelled) => {}
+                Err(e) => set_report_status.set(e.message(lang)),
+            }
+        });
+    };
+
+    // Header search over the article and the loaded annotations
+    let (search_input, set_search_input) = create_signal(String::new());
+    let query = create_memo(move |_| Query::new(&search_input.get()));
+
+    let threads = create_memo(move |_| store.with(|items| Threads::new(items)));
+    let thread_ctx = ThreadCtx {
+        threads,
+        focused,
+        set_focused,
+        collapsed,
+        reply_to,
+        reply_body,
+        reply_status,
+        on_send_reply,
+        on_reveal: Callback::new(move |id: i64| {
+            set_focused.set(Some(id));
+            if let Some(article) = article_root() { highlight::reveal(&article, &id.to_string()); }
+        }),
+        on_copy_link: Callback::new(move |id: i64| {
+            if copy_link(&fragment::annotation_hash(id)) { show_toast("Link copied"); }
+        }),
+        query,
+        reported,
+        on_report,
+        on_retry_send,
+        on_discard,
+    };
+
+    // Sidebar sort order, kept across visits
+    let (sort_mode, set_sort_mode) = create_signal(SortMode::load());
+    let choose_sort = move |mode: SortMode| {
+        mode.save();
+        set_sort_mode.set(mode);
+    };
+
+    // Sidebar kind filter; `None` shows every kind
+    let (kind_filter, set_kind_filter) = create_signal::<Option<AnnotationKind>>(None);
+
+    // Search matches: every occurrence in the article, then each matching
+    // annotation in sidebar order. Next/previous cycles through both.
+    let (article_hits, set_article_hits) = create_signal(0usize);
+    let (hit_index, set_hit_index) = create_signal::<Option<usize>>(None);
+    create_effect(move |_| {
+        let q = query.get();
+        set_hit_index.set(None);
+        let Some(article) = article_root() else { return };
+        highlight::clear_search_hits(&article);
+        // wrapping a match leaves the plain text, and so later offsets, unchanged
+        let plain = dom_text::plain_text(&article);
+        let mut count = 0;
+        for (start, end) in q.find_all(&plain) {
+            if let Some(range) = dom_text::range_from_offsets(&article, start, end) {
+                highlight::mark_search_hit(&range, count);
+                count += 1;
+            }
+        }
+        set_article_hits.set(count);
+    });
+    let annotation_hits = create_memo(move |_| {
+        let q = query.get();
+        if q.is_empty() { return vec![]; }
+        let orphaned = orphans.get();
+        store.with(|items| threads.with(|threads| {
+            let mut roots: Vec<&Annotation> = items.iter()
+                .filter(|a| a.parent_id.is_none() && !orphaned.iter().any(|(o, _)| *o == a.id))
+                .filter(|a| kind_filter.get().is_none_or(|k| a.kind == k))
+                .collect();
+            positions.with(|p| sort::sort_roots(&mut roots, sort_mode.get(), threads, p));
+            let by_id: HashMap<i64, &Annotation> = items.iter().map(|a| (a.id, a)).collect();
+            roots.iter()
+                .flat_map(|r| std::iter::once(r.id).chain(threads.descendants(r.id)))
+                .filter(|id| by_id.get(id).is_some_and(|a| q.matches(a)))
+                .collect::<Vec<i64>>()
+        }))
+    });
+    let hit_total = move || article_hits.get() + annotation_hits.with(Vec::len);
+    let go_to_hit = move |step: isize| {
+        let total = untrack(hit_total);
+        if total == 0 { return; }
+        let next = match hit_index.get_untracked() {
+            Some(i) => (i as isize + step).rem_euclid(total as isize) as usize,
+            None if step < 0 => total - 1,
+            None => 0,
+        };
+        set_hit_index.set(Some(next));
+        let Some(article) = article_root() else { return };
+        let in_article = article_hits.get_untracked();
+        if next < in_article {
+            highlight::show_search_hit(&article, Some(next));
+            return;
+        }
+        highlight::show_search_hit(&article, None);
+        let id = annotation_hits.with_untracked(|hits| hits[next - in_article]);
+        // open every collapsed thread above the match
+        let mut parent = store.get_untracked(id).and_then(|a| a.parent_id);
+        while let Some(p) = parent {
+            collapsed.update(|c| { c.remove(&p); });
+            parent = store.get_untracked(p).and_then(|a| a.parent_id);
+        }
+        set_sidebar_open.set(true);
+        set_focused.set(Some(id));
+        request_animation_frame(move || scroll_to_card(id));
+    };
+
+    // Infinite scroll: the sentinel after the list loads the next page
+    // while it is in view. Merged pages only add highlights, so those
+    // already anchored stay put.
+    let (loading_more, set_loading_more) = create_signal(false);
+    let (page_error, set_page_error) = create_signal::<Option<String>>(None);
+    let (sentinel_visible, set_sentinel_visible) = create_signal(false);
+    let sentinel_ref = create_node_ref::<html::Div>();
+    let load_more = move || {
+        let Some(slug) = (*slug_memo.get_untracked()).clone() else { return };
+        set_loading_more.set(true);
+        set_page_error.set(None);
+        spawn_local(async move {
+            let after = cursor.get_value();
+            match api.get_value().list(&slug, after.as_deref(), Some(paging::PAGE_SIZE)).await {
+                Ok(page) => {
+                    set_more.set(paging::has_more(page.len()));
+                    if let Some(next) = paging::next_cursor(&page) { cursor.set_value(Some(next)); }
+                    latest.update_value(|l| live::advance(l, &page));
+                    store.merge(page);
+                }
+                Err(ApiError::Cancelled) => return,
+                Err(e) => set_page_error.set(Some(e.message(lang))),
+            }
+            set_loading_more.set(false);
+        });
+    };
+    create_effect(move |observer: Option<Option<web_sys::IntersectionObserver>>| {
+        if let Some(Some(observer)) = observer { return Some(observer); }
+        let el = sentinel_ref.get()?;
+        let observer = paging::observe(&el, move |visible| set_sentinel_visible.set(visible))?;
+        let disconnect = observer.clone();
+        on_cleanup(move || disconnect.disconnect());
+        Some(observer)
+    });
+    create_effect(move |_| {
+        if sentinel_visible.get() && more.get() && !loading_more.get() && page_error.get().is_none() {
+            load_more();
+        }
+    });
+
+    // Live updates: once every page is loaded, poll for newer rows. They
+    // are merged into the list and highlights right away, and a pill
+    // counts the ones not looked at yet instead of scrolling to them.
+    let live_started = Rc::new(Cell::new(false));
+    create_effect(move |_| {
+        if annotations.get().is_none() || live_started.get() { return; }
+        let Some(slug) = (*slug_memo.get_untracked()).clone() else { return };
+        live_started.set(true);
+        let client = api.get_value();
+        spawn_local(async move {
+            let mut failures = 0;
+            let mut wait = live::interval(0);
+            loop {
+                retry::sleep(wait).await;
+                if client.is_cancelled() { break; }
+                wait = live::interval(failures);
+                if more.get_untracked() || !live::should_poll() { continue; }
+                let after = latest.get_value().map(|l| live::poll_after(&l));
+                match client.list(&slug, after.as_deref(), Some(api::LIST_LIMIT_MAX)).await {
+                    Ok(page) => {
+                        failures = 0;
+                        // a full page may have more behind it
+                        if page.len() >= api::LIST_LIMIT_MAX as usize { wait = Duration::ZERO; }
+                        latest.update_value(|l| live::advance(l, &page));
+                        let fresh: Vec<i64> = page.iter().map(|a| a.id).filter(|id| store.get_untracked(*id).is_none()).collect();
+                        store.merge(page);
+                        if !fresh.is_empty() { set_unseen.update(|u| u.extend(fresh)); }
+                    }
+                    Err(ApiError::Cancelled) => break,
+                    Err(_) => {
+                        failures += 1;
+                        wait = live::interval(failures);
+                    }
+                }
+            }
+        });
+    });
+    let show_unseen = move |_| {
+        let first = unseen.with_untracked(|u| {
+            store.with(|items| u.iter().filter_map(|id| items.iter().find(|a| a.id == *id)).min_by_key(|a| a.created_at.clone()).map(|a| a.id))
+        });
+        set_unseen.set(vec![]);
+        if let Some(id) = first {
+            set_focused.set(Some(id));
+            scroll_to_card(id);
+        }
+    };
+
+    // Render sidebar list. Cards are keyed, and the lists they come from
+    // are memos, so a poll that brings one new row adds one card instead of
+    // rebuilding the sidebar.
+    let has_items = create_memo(move |_| store.with(|items| !items.is_empty()));
+    let sorted_roots = create_memo(move |_| {
+        let orphaned = orphans.get();
+        store.with(|items| threads.with(|threads| {
+            let mut roots: Vec<&Annotation> = items.iter()
+                .filter(|a| a.parent_id.is_none() && !orphaned.iter().any(|(o, _)| *o == a.id))
+                .filter(|a| kind_filter.get().is_none_or(|k| a.kind == k))
+                .collect();
+            positions.with(|p| sort::sort_roots(&mut roots, sort_mode.get(), threads, p));
+            roots.into_iter().cloned().collect::<Vec<Annotation>>()
+        }))
+    });
+    // a discarded own annotation may have been orphaned
+    let orphan_rows = create_memo(move |_| {
+        orphans.with(|orphaned| store.with(|items| {
+            orphaned.iter()
+                .filter_map(|(id, reason)| Some((items.iter().find(|a| a.id == *id)?.clone(), *reason)))
+                .collect::<Vec<(Annotation, AnchorError)>>()
+        }))
+    });
+    let list_view = move || {
+        // a failed load still lists our own annotations
+        annotations.with(Option::is_some).then_some(())?;
+        Some(if !has_items.get() {
+            view! { <div class="item">No comments yet.</div> }.into_view()
+        } else {
+            // roots render as recursive threads; orphans get their own section
+            view! {
+              <For each=move || sorted_roots.get() key=thread::card_key
+                children=move |r| thread::render_thread(&r, thread_ctx) />
+              {move || (!orphan_rows.with(Vec::is_empty)).then(|| view! {
+                <section class="orphans" aria-label="Orphaned annotations">
+                  <h3 class="orphans-title">{move || format!("Orphaned ({})", orphan_rows.with(Vec::len))}</h3>
+                  <For each=move || orphan_rows.get() key=|(a, _)| thread::card_key(a)
+                    children=move |(a, reason)| view! {
+                      <div class="item orphan">
+                        <div class="anno-quote">{"“"}{a.quote.clone().unwrap_or_default()}{"”"}</div>
+                        <div class="orphan-reason">{reason.to_string()}</div>
+                        <div class="meta">{a.author()}</div>
+                        <div class="anno-body">{sanitize::render_body(&a.body_html)}</div>
+                        {thread::render_replies(a.id, thread_ctx)}
+                      </div>
+                    } />
+                </section>
+              })}
+            }.into_view()
         })
     };
 
-    // Provide slug via memo for handlers
-    let slug_memo = create_memo(move |_| slug.clone());
+    // Insert the reader's annotation right away and reconcile it with the
+    // server's answer; on failure the mark is unwrapped and the composer
+    // reopens with what was typed
+    let send_annotation = move || {
+        let (Some(slug), Some(env)) = ((*slug_memo.get_untracked()).clone(), compose_env.get_untracked()) else { return };
+        let source = input_body.get_untracked();
+        if source.trim().is_empty() {
+            set_field_error.set(Some(Field::Body));
+            set_status.set(Status::EmptyBody.message(lang));
+            return;
+        }
+        let quote = compose_quote.get_untracked();
+        let name = input_name.get_untracked();
+        let kind = input_kind.get_untracked();
+        // JavaScript string length, as the server measures it
+        if name.encode_utf16().count() > DISPLAY_NAME_MAX {
+            set_field_error.set(ApiError::TooLong.field());
+            set_status.set(ApiError::TooLong.message(lang));
+            return;
+        }
+        let idempotency_key = match retry::new_idempotency_key() {
+            Ok(key) => key,
+            Err(e) => {
+                set_status.set(Status::NoIdempotencyKey(e).message(lang));
+                return;
+            }
+        };
+        set_field_error.set(None);
+        let body = markdown::to_html(&source);
+        let new = NewAnnotation {
+            post_slug: slug.clone(),
+            display_name: (!name.is_empty()).then(|| name.clone()),
+            body_html: body,
+            selectors: env.clone(),
+            quote: quote.clone(),
+            kind,
+            parent_id: None,
+            idempotency_key,
+        };
+        // the own-draft marks become this entry's highlight before the
+        // anchoring effect sees it, or it would be highlighted twice
+        let local_id = store.insert_provisional_with(new.provisional(now_timestamp()), |local_id| {
+            anchored.update_value(|state| state.claim(local_id));
+            if let Some(article) = article_root() {
+                highlight::relabel(&article, OWN_DRAFT_ID, &local_id.to_string());
+            }
+        });
+        draft::remove(&slug, &env);
+        collapse_selection();
+        set_compose_open.set(false);
+        set_input_body.set(String::new());
+        set_input_name.set(String::new());
+        set_input_kind.set(AnnotationKind::default());
+        // behind anything still queued, so nothing overtakes an older annotation
+        if !outbox::is_online() || outbox::has_pending() {
+            queue_offline(new, local_id);
+            flush_outbox();
+            return;
+        }
+        set_status.set(Status::Sending.message(lang));
+        spawn_local(async move {
+            match api.get_value().submit(&bot.get_value(), &new).await {
+                Ok(created) => {
+                    let id = created.id;
+                    confirm_local(local_id, created);
+                    let held = store.get_untracked(id).is_some_and(|a| a.is_held());
+                    set_status.set(if held { Status::AwaitingModeration } else { Status::Sent }.message(lang));
+                }
+                Err(ApiError::Network(_)) => queue_offline(new, local_id),
+                Err(ApiError::Cancelled) => {}
+                Err(e) => {
+                    store.discard(local_id);
+                    if let Some(article) = article_root() {
+                        highlight::remove_highlight(&article, &local_id.to_string());
+                        let plain = dom_text::plain_text(&article);
+                        let range = anchoring::anchor(&plain, &env.target.selector).ok()
+                            .and_then(|(start, end)| dom_text::range_from_offsets(&article, start, end));
+                        if let Some(range) = range {
+                            highlight::highlight_range(&range, OWN_DRAFT_ID, &format!("{} anno--own", kind.mark_class()));
+                        }
+                    }
+                    set_compose_env.set(Some(new.selectors));
+                    set_compose_quote.set(quote);
+                    set_input_name.set(name);
+                    set_input_kind.set(kind);
+                    set_input_body.set(source);
+                    set_compose_open.set(true);
+                    set_sidebar_open.set(true);
+                    set_field_error.set(e.field());
+                    set_status.set(e.message(lang));
+                }
+            }
+        });
+    };
 
     view! {
         <header class="site-header">
@@ -340,7 +1015,28 @@ pub fn App() -> impl IntoView {
             <div class="logo" aria-hidden="true"></div>
             <div class="title">Read + Anno</div>
           </div>
-          <div class="search"><input placeholder="Search articles" /></div>
+          <div class="search" role="search">
+            <input type="search" placeholder="Search articles" aria-label="Search the article and annotations"
+              prop:value=move || search_input.get()
+              on:input=move |e| set_search_input.set(event_target_value(&e))
+              on:keydown=move |e: KeyboardEvent| match e.key().as_str() {
+                "Enter" => {
+                  e.prevent_default();
+                  go_to_hit(if e.shift_key() { -1 } else { 1 });
+                }
+                "Escape" => set_search_input.set(String::new()),
+                _ => {}
+              } />
+            {move || (!query.with(Query::is_empty)).then(|| view! {
+              <span class="search-count" aria-live="polite">{move || match (hit_index.get(), hit_total()) {
+                (_, 0) => "No matches".to_string(),
+                (Some(i), total) => format!("{} / {}", i + 1, total),
+                (None, total) => format!("{} found", total),
+              }}</span>
+              <button class="btn btn-ghost" aria-label="Previous match" on:click=move |_| go_to_hit(-1)>{"↑"}</button>
+              <button class="btn btn-ghost" aria-label="Next match" on:click=move |_| go_to_hit(1)>{"↓"}</button>
+            })}
+          </div>
           <div class="actions">
             <button class="btn" on:click=move |_| set_sidebar_open.set(!sidebar_open.get()) aria-label="Toggle annotations">{"Menu"}</button>
           </div>
@@ -378,17 +1074,7 @@ pub fn App() -> impl IntoView {
               <p>{"モバイル幅では右レールがオーバーレイ表示に切り替わります。ヘッダーのボタンで開閉してください。"}</p>
 
               <div class="comments">
-                <button class="btn btn-block" on:click=move |_| {
-                  if !comments_open.get() {
-                    set_comments_open.set(true);
-                    let slug_opt = slug_memo.get().as_ref().clone();
-                    spawn_local(async move {
-                      if create_comment_draft(slug_opt.clone()).await.is_ok() {
-                        set_comment_draft_created.set(true);
-                      }
-                    });
-                  }
-                }>{move || if comments_open.get() { "Comments (open)" } else { "Open comments" }}</button>
+                <button class="btn btn-block" on:click=move |_| set_comments_open.set(true)>{move || if comments_open.get() { "Comments (open)" } else { "Open comments" }}</button>
 
                 {move || if comments_open.get() { view!{
                   <div class="comment-row">
@@ -397,7 +1083,6 @@ pub fn App() -> impl IntoView {
                       <textarea class="comment-input" placeholder="コメントを入力…" prop:value={comment_input.get()} on:input=move |e| set_comment_input.set(event_target_value(&e))></textarea>
                       <div class="comment-actions">
                         <button class="btn btn-primary">Post</button>
-                        {move || if comment_draft_created.get() { view!{ <span style="font-size:12px;opacity:.8">draft created</span> }.into_view() } else { view!{ <span></span> }.into_view() }}
                       </div>
                     </div>
                   </div>
@@ -415,47 +1100,137 @@ pub fn App() -> impl IntoView {
             <header>
               <strong>Annotations</strong>
               <div class="controls">
-                <button class="btn">Newest</button>
-                <button class="btn">By text</button>
+                <div class="sort-modes" role="group" aria-label="Sort annotations">
+                  {SortMode::ALL.into_iter().map(|mode| view! {
+                    <button
+                      class=move || if sort_mode.get() == mode { "btn is-active" } else { "btn" }
+                      aria-pressed=move || (sort_mode.get() == mode).to_string()
+                      on:click=move |_| choose_sort(mode)
+                    >{mode.label()}</button>
+                  }).collect_view()}
+                </div>
+                <select class="comment-kind" aria-label="Filter by kind" on:change=move |e| {
+                  let value = event_target_value(&e);
+                  set_kind_filter.set((!value.is_empty()).then(|| AnnotationKind::parse(&value)));
+                }>
+                  <option value="">All kinds</option>
+                  {AnnotationKind::ALL.into_iter().map(|k| view! { <option value=k.as_str()>{k.label()}</option> }).collect_view()}
+                </select>
               </div>
             </header>
+            {move || (!unseen.with(Vec::is_empty)).then(|| view! {
+              <button class="live-pill" aria-live="polite" on:click=show_unseen>
+                {move || match unseen.with(Vec::len) {
+                  1 => "1 new annotation".to_string(),
+                  n => format!("{} new annotations", n),
+                }}
+              </button>
+            })}
             <ol class="anno-list">
-              {if compose_open.get() { view!{
+              <For each=move || if compose_open.get() { vec![] } else { draft_offers.get() }
+                key=|(d, _)| draft::selector_hash(&d.env)
+                children=move |(d, anchored)| {
+                  let hash = draft::selector_hash(&d.env);
+                  match anchored {
+                    Ok(_) => view! {
+                      <li class="anno-card draft-offer" role="status">
+                        <div>{"Unsent draft on "}<span class="anno-quote">{"“"}{d.quote}{"”"}</span></div>
+                        <div class="card-actions">
+                          <button class="btn btn-primary" on:click={
+                            let hash = hash.clone();
+                            move |_| restore_draft(hash.clone())
+                          }>Restore</button>
+                          <button class="btn" on:click=move |_| discard_draft(hash.clone())>Discard</button>
+                        </div>
+                      </li>
+                    },
+                    Err(reason) => view! {
+                      <li class="anno-card draft-offer orphan" role="status">
+                        <div>{"Unsent draft on "}<span class="anno-quote">{"“"}{d.quote}{"”"}</span></div>
+                        <div class="orphan-reason">{reason.to_string()}</div>
+                        <div class="anno-body draft-body">{d.body}</div>
+                        <div class="card-actions">
+                          <button class="btn" on:click=move |_| discard_draft(hash.clone())>Discard</button>
+                        </div>
+                      </li>
+                    },
+                  }
+                } />
+              {move || if compose_open.get() { view!{
                 <li class="anno-card">
-                  <div class="anno-quote">{"…"}{compose_quote.get()}{"…"}</div>
+                  <div class=field_class(Field::Selection, "anno-quote")>{"…"}{move || compose_quote.get()}{"…"}</div>
                   <div style="display:flex;flex-direction:column;gap:.5rem;margin-top:8px">
-                    <input placeholder="表示名 (任意)" prop:value={input_name.get()} on:input=move |e| set_input_name.set(event_target_value(&e)) class="btn" style="padding:.4rem" />
-                    <textarea placeholder="コメントを入力" prop:value={input_body.get()} on:input=move |e| set_input_body.set(event_target_value(&e)) class="btn" style="min-height:96px"></textarea>
+                    <input placeholder="表示名 (任意)" prop:value={input_name.get_untracked()} class=field_class(Field::DisplayName, "btn") style="padding:.4rem"
+                      attr:aria-invalid=move || (field_error.get() == Some(Field::DisplayName)).to_string()
+                      on:input=move |e| {
+                        set_input_name.set(event_target_value(&e));
+                        if field_error.get_untracked() == Some(Field::DisplayName) { set_field_error.set(None); }
+                      } />
+                    <select class="comment-kind" aria-label="Kind" on:change=move |e| {
+                      let kind = AnnotationKind::parse(&event_target_value(&e));
+                      set_input_kind.set(kind);
+                      // recolour the pending highlight to match
+                      if let Some(article) = article_root() {
+                        for mark in highlight::marks_for(&article, OWN_DRAFT_ID) {
+                          mark.set_class_name(&format!("{} anno--own", kind.mark_class()));
+                        }
+                      }
+                    }>
+                      {AnnotationKind::ALL.into_iter().map(|k| view! {
+                        <option value=k.as_str() prop:selected=move || input_kind.get() == k>{k.label()}</option>
+                      }).collect_view()}
+                    </select>
+                    <textarea node_ref=body_ref placeholder="コメントを入力" prop:value={input_body.get_untracked()} class=field_class(Field::Body, "btn") style="min-height:96px"
+                      attr:aria-invalid=move || (field_error.get() == Some(Field::Body)).to_string()
+                      on:input=move |e| {
+                        set_input_body.set(event_target_value(&e));
+                        if field_error.get_untracked() == Some(Field::Body) { set_field_error.set(None); }
+                      }></textarea>
+                    <div class="md-hint">{"**bold** *italic* `code` — URLs link automatically"}</div>
+                    {move || {
+                      let src = input_body.get();
+                      if src.trim().is_empty() { return None; }
+                      let links = markdown::url_count(&markdown::to_html(&src));
+                      Some(view! {
+                        <div class="md-preview" aria-label="Preview">{sanitize::render_nodes(&markdown::parse(&src))}</div>
+                        {(links > MODERATION_URL_THRESHOLD).then(|| view! {
+                          <div class="md-warning" role="status">
+                            {format!("{} links: comments with more than {} are held for moderation", links, MODERATION_URL_THRESHOLD)}
+                          </div>
+                        })}
+                      })
+                    }}
                     <div style="display:flex;gap:.5rem">
-                      <button class="btn btn-primary" on:click=move |_| {
-                        let slug_opt = slug_memo.get().as_ref().clone();
-                        if let (Some(slug), Some(env)) = (slug_opt, compose_env.get()) {
-                            let quote = compose_quote.get();
-                            let name = input_name.get();
-                            let body = input_body.get();
-                            spawn_local(async move {
-                                match post_annotation(&slug, env, &quote, if name.is_empty() { None } else { Some(name) }, body).await {
-                                    Ok(()) => {
-                                        set_status.set("Sent!".into());
-                                        annotations.refetch();
-                                        set_compose_open.set(false);
-                                        set_input_body.set(String::new());
-                                        set_input_name.set(String::new());
-                                    }
-                                    Err(e) => set_status.set(format!("Error: {}", e)),
-                                }
-                            });
+                      <button class="btn btn-primary" on:click=move |_| send_annotation()>送信</button>
+                      <button class="btn" on:click=move |_| {
+                        if let Some(article) = article_root() { highlight::remove_highlight(&article, OWN_DRAFT_ID); }
+                        collapse_selection();
+                        if let (Some(slug), Some(env)) = ((*slug_memo.get_untracked()).clone(), compose_env.get_untracked()) {
+                          draft::remove(&slug, &env);
                         }
-                      }>送信</button>
-                      <button class="btn" on:click=move |_| { set_compose_open.set(false); set_status.set(String::new()); }>キャンセル</button>
+                        set_compose_open.set(false);
+                        set_status.set(String::new());
+                      }>キャンセル</button>
                     </div>
-                    <div class="status" style="color:#666;font-size:.9rem">{status.get()}</div>
+                    <div class="status" style="color:#666;font-size:.9rem">{move || status.get()}</div>
                   </div>
                 </li>
-              }.into_view()} else { view!{ <li class="anno-card">{status.get()}</li> }.into_view()}}
+              }.into_view()} else { view!{ <li class="anno-card">{move || status.get()}</li> }.into_view()}}
+              <li class="bot-check" id=botcheck::CONTAINER_ID></li>
 
               <li>
-                {list_view()}
+                {list_view}
+              </li>
+              <li class="list-sentinel" hidden=move || !more.get() aria-live="polite">
+                <div node_ref=sentinel_ref>
+                  {move || match page_error.get() {
+                    Some(message) => view! {
+                      <span>{message}</span>
+                      <button class="btn" on:click=move |_| load_more()>{"Retry"}</button>
+                    }.into_view(),
+                    None => view! { <span>{"Loading more…"}</span> }.into_view(),
+                  }}
+                </div>
               </li>
             </ol>
           </aside>
@@ -464,33 +1239,52 @@ pub fn App() -> impl IntoView {
             <div id="sel-pop" role="dialog" class="popover" style=move || format!("left: {}px; top: {}px;", pop.get().x, pop.get().y)>
               <p class="preview">{""}{pop.get().preview.clone()}</p>
               <div class="actions">
-                <button class="btn btn-primary" on:click=move |_| {
-                  wrap_selection_with_mark("anno anno--own");
-                  collapse_selection();
-                  set_pop.set(PopState { show: false, x: 0.0, y: 0.0, preview: String::new() });
-                  set_compose_open.set(true);
-                  set_sidebar_open.set(true);
-                }>Add comment</button>
+                <button class="btn btn-primary" title="Add comment (Ctrl+Alt+M)" on:click=move |_| open_composer()>Add comment</button>
                 <button class="btn" on:click=move |_| {
-                  if let Some((exact, _start, _end)) = current_selection() {
-                    if let Some(win) = window() {
-                      if let Ok(loc) = win.location().href() {
-                        let base = loc.split('#').next().unwrap_or("");
-                        let frag = format!("#:~:text={}", js_sys::encode_uri_component(&exact));
-                        let url = format!("{}{}", base, frag);
-                        let _ = win.navigator().clipboard().write_text(&url);
-                        show_toast("Link copied");
-                      }
+                  if let Some((_exact, start, end)) = current_selection() {
+                    let plain = article_plain_text().unwrap_or_default();
+                    let directive = TextDirective::for_range(&plain, start, end);
+                    if copy_link(&format!("#{}", directive.to_fragment())) {
+                      show_toast("Link copied");
                     }
                   }
                 }>Copy link</button>
                 <button class="btn btn-ghost" on:click=move |_| {
-                  set_pop.set(PopState { show: false, x: 0.0, y: 0.0, preview: String::new() });
+                  hide_pop();
                   collapse_selection();
                 }>Cancel</button>
               </div>
             </div>
           }.into_view()} else { view!{ <div class="sr-only"></div> }.into_view()}}
+          <dialog node_ref=report_ref class="report-dialog" aria-labelledby="report-title" on:close=move |_| set_report_target.set(None)>
+            <form on:submit=move |e| { e.prevent_default(); submit_report(); }>
+              <h2 id="report-title">{"Report annotation"}</h2>
+              <fieldset>
+                <legend>{"What is wrong with it?"}</legend>
+                {Reason::ALL.into_iter().map(|reason| view! {
+                  <label class="report-reason">
+                    <input type="radio" name="report-reason" value=reason.as_str()
+                      prop:checked=move || report_reason.get() == reason
+                      on:change=move |_| set_report_reason.set(reason) />
+                    {reason.label()}
+                  </label>
+                }).collect_view()}
+              </fieldset>
+              <label class="report-note">
+                {move || if report_reason.get() == Reason::Other { "Details (required)" } else { "Details (optional)" }}
+                <textarea maxlength=report::NOTE_MAX.to_string()
+                  prop:value=move || report_note.get()
+                  on:input=move |e| set_report_note.set(event_target_value(&e))></textarea>
+              </label>
+              <div class="status" role="alert">{move || report_status.get()}</div>
+              <div class="report-actions">
+                <button type="button" class="btn" on:click=move |_| close_report()>{"Cancel"}</button>
+                <button type="submit" class="btn btn-primary" disabled=move || report_sending.get()>
+                  {move || if report_sending.get() { "Reporting…" } else { "Report" }}
+                </button>
+              </div>
+            </form>
+          </dialog>
           {move || if toast_show.get() { view!{ <div class="toast">{toast_msg.get()}</div> }.into_view() } else { view!{ <div class="sr-only"></div> }.into_view() }}
         </main>
     }
@@ -0,0 +1,170 @@
+//! Bot checks for posting annotations.
+//!
+//! Pages that carry `<meta name="turnstile-site-key" content="…">` get the
+//! real Cloudflare Turnstile widget; without a site key the mock provider
+//! hands out tokens the server accepts under `TURNSTILE_MODE=mock`, so
+//! local development works offline. Tokens are single-use and fetched just
+//! before each request.
+//!
+//! The widget renders into `#bot-check`, which the app places next to the
+//! composers so that an interactive challenge is seen.
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use js_sys::{Function, Object, Promise, Reflect};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{window, Element};
+
+const SITE_KEY_META: &str = "meta[name=\"turnstile-site-key\"]";
+const SCRIPT_URL: &str = "https://challenges.cloudflare.com/turnstile/v0/api.js?render=explicit";
+pub const CONTAINER_ID: &str = "bot-check";
+/// Longest wait for a token, including a challenge the reader must solve.
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(60);
+/// Accepted by the server's mock verifier.
+const MOCK_TOKEN: &str = "ok";
+
+thread_local! {
+    // the widget script is loaded once, on first use
+    static SCRIPT: RefCell<Option<Promise>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BotCheck {
+    Turnstile { site_key: String },
+    Mock,
+}
+
+impl BotCheck {
+    /// The provider the page is configured for.
+    pub fn from_page() -> BotCheck {
+        let site_key = window()
+            .and_then(|w| w.document())
+            .and_then(|d| d.query_selector(SITE_KEY_META).ok().flatten())
+            .and_then(|meta| meta.get_attribute("content"))
+            .filter(|key| !key.trim().is_empty());
+        match site_key {
+            Some(site_key) => BotCheck::Turnstile { site_key },
+            None => BotCheck::Mock,
+        }
+    }
+
+    /// A fresh token for one request.
+    pub async fn token(&self) -> Result<String, String> {
+        match self {
+            BotCheck::Turnstile { site_key } => turnstile_token(site_key).await,
+            BotCheck::Mock => Ok(MOCK_TOKEN.into()),
+        }
+    }
+}
+
+fn load_script() -> Promise {
+    Promise::new(&mut |resolve, reject| {
+        let doc = window().and_then(|w| w.document());
+        let script = doc.as_ref().and_then(|d| d.create_element("script").ok());
+        let (Some(body), Some(script)) = (doc.and_then(|d| d.body()), script) else {
+            let _ = reject.call0(&JsValue::NULL);
+            return;
+        };
+        let _ = script.set_attribute("src", SCRIPT_URL);
+        let _ = script.set_attribute("async", "");
+        let _ = script.add_event_listener_with_callback("load", &resolve);
+        let _ = script.add_event_listener_with_callback("error", &reject);
+        let _ = body.append_child(&script);
+    })
+}
+
+async fn turnstile_api() -> Result<JsValue, String> {
+    let win: JsValue = window().ok_or("no window")?.into();
+    let api = || Reflect::get(&win, &"turnstile".into()).ok().filter(|api| !api.is_undefined());
+    if let Some(api) = api() {
+        return Ok(api);
+    }
+    let loading = SCRIPT.with(|s| s.borrow_mut().get_or_insert_with(load_script).clone());
+    if JsFuture::from(loading).await.is_err() {
+        // let the next attempt try again, e.g. after coming back online
+        SCRIPT.with(|s| s.borrow_mut().take());
+        return Err("could not load the bot check".into());
+    }
+    api().ok_or_else(|| "could not load the bot check".into())
+}
+
+fn container() -> Option<Element> {
+    let doc = window()?.document()?;
+    if let Some(el) = doc.get_element_by_id(CONTAINER_ID) {
+        return Some(el);
+    }
+    let el = doc.create_element("div").ok()?;
+    el.set_id(CONTAINER_ID);
+    doc.body()?.append_child(&el).ok()?;
+    Some(el)
+}
+
+fn call(api: &JsValue, method: &str, args: &[&JsValue]) -> Result<JsValue, JsValue> {
+    let f: Function = Reflect::get(api, &method.into())?.dyn_into()?;
+    match args {
+        [a] => f.call1(api, a),
+        [a, b] => f.call2(api, a, b),
+        _ => f.call0(api),
+    }
+}
+
+// Render a one-off widget and wait for its callback, at most
+// `TOKEN_TIMEOUT`. The widget only shows itself when Turnstile wants the
+// reader to interact.
+async fn turnstile_token(site_key: &str) -> Result<String, String> {
+    let api = turnstile_api().await?;
+    let win = window().ok_or("no window")?;
+    let container = container().ok_or("no document")?;
+    let widget = Rc::new(RefCell::new(JsValue::UNDEFINED));
+    let mut timer = None;
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let opts = Object::new();
+        let fail = |reason: &'static str| {
+            let reject = reject.clone();
+            Closure::once_into_js(move || reject.call1(&JsValue::NULL, &reason.into()))
+        };
+        let on_token = Closure::once_into_js(move |token: JsValue| resolve.call1(&JsValue::NULL, &token));
+        // the reader may be at a reply composer further down
+        let shown = container.clone();
+        let on_interactive = Closure::once_into_js(move || shown.scroll_into_view());
+        let entries: [(&str, JsValue); 7] = [
+            ("sitekey", site_key.into()),
+            ("appearance", "interaction-only".into()),
+            ("callback", on_token),
+            ("before-interactive-callback", on_interactive),
+            ("error-callback", fail("the bot check failed")),
+            ("expired-callback", fail("the bot check expired")),
+            ("timeout-callback", fail("the bot check timed out")),
+        ];
+        for (key, value) in entries {
+            let _ = Reflect::set(&opts, &key.into(), &value);
+        }
+        match call(&api, "render", &[&container, &opts]) {
+            Ok(id) => *widget.borrow_mut() = id,
+            Err(_) => {
+                let _ = reject.call1(&JsValue::NULL, &"the bot check failed".into());
+            }
+        }
+        timer = win
+            .set_timeout_with_callback_and_timeout_and_arguments_0(
+                fail("the bot check timed out").unchecked_ref(),
+                TOKEN_TIMEOUT.as_millis() as i32,
+            )
+            .ok();
+    });
+    let result = JsFuture::from(promise).await;
+    if let Some(timer) = timer {
+        win.clear_timeout_with_handle(timer);
+    }
+    let id = widget.borrow().clone();
+    if !id.is_undefined() {
+        let _ = call(&api, "remove", &[&id]);
+    }
+    match result {
+        Ok(token) => token.as_string().ok_or_else(|| "the bot check failed".into()),
+        Err(reason) => Err(reason.as_string().unwrap_or_else(|| "the bot check failed".into())),
+    }
+}
@@ -0,0 +1,118 @@
+use wasm_bindgen::JsCast;
+use web_sys::{window, Element, Node, Range, Text};
+
+use crate::offset::{self, TextOffset, TextUnit};
+
+// NodeFilter.SHOW_TEXT
+const SHOW_TEXT: u32 = 0x4;
+
+pub fn article_root() -> Option<Element> {
+    let doc = window()?.document()?;
+    doc.query_selector("article").ok().flatten()
+}
+
+/// All text nodes below `root`, in document order.
+pub fn text_nodes(root: &Node) -> Vec<Text> {
+    let mut out = vec![];
+    let Some(doc) = window().and_then(|w| w.document()) else { return out };
+    let Ok(walker) = doc.create_tree_walker_with_what_to_show(root, SHOW_TEXT) else { return out };
+    while let Ok(Some(node)) = walker.next_node() {
+        if let Ok(text) = node.dyn_into::<Text>() {
+            out.push(text);
+        }
+    }
+    out
+}
+
+/// Concatenated text of every text node below `root`; offsets returned by
+/// [`boundary_offset`] are codepoints into this string.
+pub fn plain_text(root: &Node) -> String {
+    text_nodes(root).iter().map(|t| t.data()).collect()
+}
+
+fn char_len(text: &Text) -> usize {
+    offset::len_in(&text.data(), TextUnit::Codepoint)
+}
+
+fn follows(reference: &Node, other: &Node) -> bool {
+    reference.compare_document_position(other) & Node::DOCUMENT_POSITION_FOLLOWING != 0
+}
+
+/// Codepoint offset within `root`'s plain text of the DOM boundary point
+/// (`container`, `offset`). Handles both text-node and element containers.
+pub fn boundary_offset(root: &Node, container: &Node, offset: u32) -> Option<usize> {
+    if !root.contains(Some(container)) {
+        return None;
+    }
+    let nodes = text_nodes(root);
+    let mut total = 0;
+    if let Some(text) = container.dyn_ref::<Text>() {
+        for n in &nodes {
+            if n == text {
+                // DOM offsets inside a text node count UTF-16 code units
+                return Some(total + TextOffset::utf16(offset as usize).to_codepoint(&n.data())?);
+            }
+            total += char_len(n);
+        }
+        return None;
+    }
+    match container.child_nodes().item(offset) {
+        // Boundary sits right before `child`: count text that precedes it.
+        Some(child) => {
+            for n in &nodes {
+                let node: &Node = n.as_ref();
+                if child.contains(Some(node)) || follows(&child, node) {
+                    break;
+                }
+                total += char_len(n);
+            }
+        }
+        // Boundary sits after the container's last child.
+        None => {
+            for n in &nodes {
+                let node: &Node = n.as_ref();
+                if !container.contains(Some(node)) && follows(container, node) {
+                    break;
+                }
+                total += char_len(n);
+            }
+        }
+    }
+    Some(total)
+}
+
+/// Start and end codepoint offsets of `range` within `root`.
+pub fn range_offsets(root: &Node, range: &Range) -> Option<(usize, usize)> {
+    let start = boundary_offset(root, &range.start_container().ok()?, range.start_offset().ok()?)?;
+    let end = boundary_offset(root, &range.end_container().ok()?, range.end_offset().ok()?)?;
+    Some((start.min(end), start.max(end)))
+}
+
+// Text node and UTF-16 offset for a codepoint offset. At a node boundary a
+// start prefers the following node and an end the preceding one, so ranges
+// never begin or end on an empty edge.
+fn locate(nodes: &[Text], offset: usize, is_start: bool) -> Option<(Text, u32)> {
+    let mut total = 0;
+    for n in nodes {
+        let len = char_len(n);
+        let inside = if is_start { offset < total + len } else { offset <= total + len && offset > total };
+        if inside {
+            let dom_offset = TextOffset::codepoint(offset - total).to_unit(&n.data(), TextUnit::Utf16)?;
+            return Some((n.clone(), dom_offset.value as u32));
+        }
+        total += len;
+    }
+    let last = nodes.last()?;
+    (offset == total).then(|| (last.clone(), last.length()))
+}
+
+/// DOM range covering the codepoint offsets `start..end` of `root`'s plain text.
+pub fn range_from_offsets(root: &Node, start: usize, end: usize) -> Option<Range> {
+    let nodes = text_nodes(root);
+    let (start_node, start_offset) = locate(&nodes, start, true)?;
+    let (end_node, end_offset) = locate(&nodes, end, false)?;
+    let range = window()?.document()?.create_range().ok()?;
+    range.set_start(&start_node, start_offset).ok()?;
+    range.set_end(&end_node, end_offset).ok()?;
+    Some(range)
+}
@@ -0,0 +1,88 @@
+//! Unsent composer drafts kept in `localStorage`.
+//!
+//! Keys are `anno-draft:<slug>:<hash>`, where the hash covers the target
+//! selectors, so each passage of a post keeps its own draft.
+use serde::{Deserialize, Serialize};
+use web_sys::{window, Storage};
+
+use crate::kind::AnnotationKind;
+use crate::selector::Envelope;
+
+const KEY_PREFIX: &str = "anno-draft";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Draft {
+    pub env: Envelope,
+    pub quote: String,
+    pub name: String,
+    pub body: String,
+    #[serde(default)]
+    pub kind: AnnotationKind,
+    /// `Date.now()` at the last save.
+    pub saved_at: f64,
+}
+
+// FNV-1a; stable across builds, unlike `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+pub fn selector_hash(env: &Envelope) -> String {
+    let selectors = serde_json::to_string(&env.target.selector).unwrap_or_default();
+    format!("{:016x}", fnv1a(selectors.as_bytes()))
+}
+
+pub fn key(slug: &str, env: &Envelope) -> String {
+    format!("{}:{}:{}", KEY_PREFIX, slug, selector_hash(env))
+}
+
+fn storage() -> Option<Storage> {
+    window()?.local_storage().ok().flatten()
+}
+
+pub fn save(slug: &str, draft: &Draft) {
+    let (Some(store), Ok(raw)) = (storage(), serde_json::to_string(draft)) else { return };
+    // storage can be full or disabled; a lost draft is not worth an error
+    let _ = store.set_item(&key(slug, &draft.env), &raw);
+}
+
+pub fn remove(slug: &str, env: &Envelope) {
+    if let Some(store) = storage() {
+        let _ = store.remove_item(&key(slug, env));
+    }
+}
+
+/// Drafts saved for `slug`, most recent first.
+pub fn load_all(slug: &str) -> Vec<Draft> {
+    let Some(store) = storage() else { return vec![] };
+    let prefix = format!("{}:{}:", KEY_PREFIX, slug);
+    let len = store.length().unwrap_or(0);
+    let mut drafts: Vec<Draft> = (0..len)
+        .filter_map(|i| store.key(i).ok().flatten())
+        .filter(|k| k.starts_with(&prefix))
+        .filter_map(|k| store.get_item(&k).ok().flatten())
+        .filter_map(|raw| serde_json::from_str(&raw).ok())
+        .collect();
+    drafts.sort_by(|a, b| b.saved_at.total_cmp(&a.saved_at));
+    drafts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selector::{OneOrMany, Selector};
+
+    fn env(exact: &str) -> Envelope {
+        let quote = Selector::TextQuote { exact: exact.into(), prefix: None, suffix: None, refined_by: None };
+        Envelope::new("/posts/a".into(), vec![quote])
+    }
+
+    #[test]
+    fn keys_follow_the_selectors_only() {
+        let mut with_motivation = env("猫");
+        with_motivation.motivation = Some(OneOrMany::One("commenting".into()));
+        assert_eq!(key("a", &env("猫")), key("a", &with_motivation));
+        assert_ne!(key("a", &env("猫")), key("a", &env("犬")));
+        assert!(key("a", &env("猫")).starts_with("anno-draft:a:"));
+    }
+}
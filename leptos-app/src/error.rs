@@ -0,0 +1,292 @@
+//! Errors from the annotations API, mirroring `ApiErrorCode` in
+//! `src/lib/types.ts`.
+//!
+//! Error responses are `{ "error": <code>, "message"?: string }`; `conflict`
+//! also carries the `id` of the annotation an idempotency key already
+//! created.
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Longest display name the server accepts (`DISPLAY_NAME_MAX`).
+pub const DISPLAY_NAME_MAX: usize = 32;
+/// Wait assumed when a `rate_limited` response has no `Retry-After`; the
+/// server allows one annotation per visitor every 15 seconds.
+pub const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(15);
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum ApiErrorCode {
+    InvalidInput,
+    TooLong,
+    TooManyLinks,
+    MissingSelector,
+    Unauthorized,
+    BadOrigin,
+    BotSuspected,
+    RateLimited,
+    NotFound,
+    Conflict,
+    InternalError,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: ApiErrorCode,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    id: Option<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ApiError {
+    InvalidInput(Option<String>),
+    TooLong,
+    TooManyLinks,
+    MissingSelector,
+    Unauthorized,
+    BadOrigin,
+    BotSuspected,
+    RateLimited { retry_after: Option<Duration> },
+    NotFound,
+    /// The idempotency key was already used; `id` is the stored annotation
+    /// once the first request has finished.
+    Conflict { id: Option<i64> },
+    Internal(Option<String>),
+    /// A failed response without an API error body.
+    Http(u16),
+    /// The request never got a response.
+    Network(String),
+    /// No bot-check token could be had, so nothing was sent.
+    BotCheck(String),
+    /// Aborted because its owner went away.
+    Cancelled,
+}
+
+/// Composer fields a validation error can be pinned to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Body,
+    DisplayName,
+    Selection,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lang {
+    Ja,
+    En,
+}
+
+impl Lang {
+    /// Japanese for readers whose browser prefers it, English otherwise.
+    pub fn detect() -> Lang {
+        let lang = web_sys::window().and_then(|w| w.navigator().language()).unwrap_or_default();
+        if lang.starts_with("ja") { Lang::Ja } else { Lang::En }
+    }
+}
+
+/// Progress and client-side validation messages shown by the composers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    Sending,
+    Sent,
+    AwaitingModeration,
+    SavedOffline,
+    /// Waiting behind older queued annotations, or for the server to be
+    /// reachable again.
+    Queued,
+    EmptyBody,
+    /// Replies need their parent's server id and selectors.
+    ParentUnsent,
+    ParentUnanchored,
+    MissingReportNote,
+    /// No idempotency key could be generated.
+    NoIdempotencyKey(String),
+}
+
+impl Status {
+    pub fn message(&self, lang: Lang) -> String {
+        let ja = lang == Lang::Ja;
+        match self {
+            Status::Sending if ja => "送信中…".into(),
+            Status::Sending => "Sending…".into(),
+            Status::Sent if ja => "送信しました。".into(),
+            Status::Sent => "Sent!".into(),
+            Status::AwaitingModeration if ja => "送信しました。承認後に公開されます。".into(),
+            Status::AwaitingModeration => "Sent — awaiting moderation".into(),
+            Status::SavedOffline if ja => "オフラインのため保存しました。接続が戻ると送信されます。".into(),
+            Status::SavedOffline => "Saved offline — it will be sent when you are back online".into(),
+            Status::Queued if ja => "送信待ちに追加しました。自動的に送信されます。".into(),
+            Status::Queued => "Queued — it will be sent automatically".into(),
+            Status::EmptyBody if ja => "コメントを入力してください。".into(),
+            Status::EmptyBody => "Write a comment first.".into(),
+            Status::ParentUnsent if ja => "このコメントはまだ送信中です。".into(),
+            Status::ParentUnsent => "This comment is still being sent.".into(),
+            Status::ParentUnanchored if ja => "このスレッドには返信できません。".into(),
+            Status::ParentUnanchored => "This thread cannot be replied to.".into(),
+            Status::MissingReportNote if ja => "問題の内容を入力してください。".into(),
+            Status::MissingReportNote => "Please say what is wrong.".into(),
+            Status::NoIdempotencyKey(detail) if ja => format!("送信できません: {}", detail),
+            Status::NoIdempotencyKey(detail) => format!("Error: {}", detail),
+        }
+    }
+}
+
+impl ApiError {
+    /// Build the error for a failed response from its status, body and
+    /// `Retry-After` header.
+    pub fn from_response(status: u16, body: &str, retry_after: Option<&str>) -> ApiError {
+        let Ok(body) = serde_json::from_str::<ErrorBody>(body) else {
+            return match status {
+                429 => ApiError::RateLimited { retry_after: retry_after.and_then(parse_retry_after) },
+                _ => ApiError::Http(status),
+            };
+        };
+        match body.error {
+            ApiErrorCode::InvalidInput if body.message.as_deref() == Some("missing selectors") => ApiError::MissingSelector,
+            ApiErrorCode::InvalidInput => ApiError::InvalidInput(body.message),
+            ApiErrorCode::TooLong => ApiError::TooLong,
+            ApiErrorCode::TooManyLinks => ApiError::TooManyLinks,
+            ApiErrorCode::MissingSelector => ApiError::MissingSelector,
+            ApiErrorCode::Unauthorized => ApiError::Unauthorized,
+            ApiErrorCode::BadOrigin => ApiError::BadOrigin,
+            ApiErrorCode::BotSuspected => ApiError::BotSuspected,
+            ApiErrorCode::RateLimited => ApiError::RateLimited { retry_after: retry_after.and_then(parse_retry_after) },
+            ApiErrorCode::NotFound => ApiError::NotFound,
+            ApiErrorCode::Conflict => ApiError::Conflict { id: body.id },
+            ApiErrorCode::InternalError => ApiError::Internal(body.message),
+            ApiErrorCode::Unknown => ApiError::Http(status),
+        }
+    }
+
+    /// Worth sending again unchanged, e.g. after a backoff.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ApiError::Network(_) | ApiError::Internal(_) | ApiError::Conflict { id: None } => true,
+            ApiError::Http(status) => *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// The connection or the server is at fault rather than the request,
+    /// so a queued annotation is worth sending again later.
+    pub fn is_outage(&self) -> bool {
+        match self {
+            ApiError::Network(_) | ApiError::Cancelled | ApiError::RateLimited { .. } | ApiError::Internal(_) => true,
+            ApiError::Http(status) => *status >= 500,
+            _ => false,
+        }
+    }
+
+    pub fn field(&self) -> Option<Field> {
+        match self {
+            ApiError::TooLong => Some(Field::DisplayName),
+            ApiError::TooManyLinks => Some(Field::Body),
+            ApiError::MissingSelector => Some(Field::Selection),
+            // "missing parent_id", "post mismatch", "invalid json", "missing
+            // slug", "missing fields" and the bare `invalid_input` for a missing
+            // required field name nothing the reader can edit; the composer
+            // refuses an empty body before sending
+            _ => None,
+        }
+    }
+
+    pub fn message(&self, lang: Lang) -> String {
+        let ja = lang == Lang::Ja;
+        match self {
+            ApiError::InvalidInput(_) if ja => "入力内容を確認してください。".into(),
+            ApiError::InvalidInput(_) => "Please check what you entered.".into(),
+            ApiError::TooLong if ja => format!("表示名は{}文字以内にしてください。", DISPLAY_NAME_MAX),
+            ApiError::TooLong => format!("Display names can be at most {} characters.", DISPLAY_NAME_MAX),
+            ApiError::TooManyLinks if ja => "リンクが多すぎます。".into(),
+            ApiError::TooManyLinks => "This comment has too many links.".into(),
+            ApiError::MissingSelector if ja => "本文の選択範囲が見つかりません。選択し直してください。".into(),
+            ApiError::MissingSelector => "The selected passage is missing; select it again.".into(),
+            ApiError::Unauthorized if ja => "この操作は許可されていません。".into(),
+            ApiError::Unauthorized => "You are not allowed to do that.".into(),
+            ApiError::BadOrigin if ja => "このページからは送信できません。再読み込みしてください。".into(),
+            ApiError::BadOrigin => "Requests from this page were refused; reload and try again.".into(),
+            ApiError::BotSuspected if ja => "ボット判定に失敗しました。もう一度お試しください。".into(),
+            ApiError::BotSuspected => "The bot check failed; please try again.".into(),
+            ApiError::RateLimited { retry_after } => {
+                let secs = retry_after.unwrap_or(DEFAULT_RETRY_AFTER).as_secs().max(1);
+                if ja {
+                    format!("投稿が続いています。{}秒後にもう一度お試しください。", secs)
+                } else {
+                    format!("You are posting too quickly; try again in {} seconds.", secs)
+                }
+            }
+            ApiError::NotFound if ja => "記事が見つかりません。".into(),
+            ApiError::NotFound => "This post could not be found.".into(),
+            ApiError::Conflict { .. } if ja => "この注釈はすでに送信済みです。".into(),
+            ApiError::Conflict { .. } => "This annotation was already sent.".into(),
+            ApiError::Internal(_) | ApiError::Http(_) if ja => "サーバーでエラーが発生しました。しばらくしてからお試しください。".into(),
+            ApiError::Internal(_) | ApiError::Http(_) => "Something went wrong on the server; try again later.".into(),
+            ApiError::Network(_) if ja => "サーバーに接続できません。".into(),
+            ApiError::Network(_) => "Could not reach the server.".into(),
+            ApiError::BotCheck(_) if ja => "ボット判定を完了できませんでした。もう一度お試しください。".into(),
+            ApiError::BotCheck(_) => "The bot check could not be completed; please try again.".into(),
+            ApiError::Cancelled if ja => "リクエストは中止されました。".into(),
+            ApiError::Cancelled => "The request was cancelled.".into(),
+        }
+    }
+}
+
+/// `Retry-After` as delay-seconds or an HTTP date.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = js_sys::Date::parse(value);
+    if at.is_nan() {
+        return None;
+    }
+    Some(Duration::from_millis((at - js_sys::Date::now()).max(0.0) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_error_bodies() {
+        assert_eq!(ApiError::from_response(400, r#"{"error":"too_long"}"#, None), ApiError::TooLong);
+        assert_eq!(
+            ApiError::from_response(400, r#"{"error":"invalid_input","message":"missing selectors"}"#, None),
+            ApiError::MissingSelector
+        );
+        assert_eq!(ApiError::from_response(409, r#"{"error":"conflict","id":12}"#, None), ApiError::Conflict { id: Some(12) });
+        assert_eq!(
+            ApiError::from_response(429, r#"{"error":"rate_limited"}"#, Some("20")),
+            ApiError::RateLimited { retry_after: Some(Duration::from_secs(20)) }
+        );
+        assert_eq!(ApiError::from_response(502, "<html>", None), ApiError::Http(502));
+        assert_eq!(ApiError::from_response(400, r#"{"error":"brand_new"}"#, None), ApiError::Http(400));
+        assert!(ApiError::Http(503).is_outage() && ApiError::RateLimited { retry_after: None }.is_outage());
+        for refused in [ApiError::TooManyLinks, ApiError::BadOrigin, ApiError::Unauthorized, ApiError::BotSuspected, ApiError::Http(403)] {
+            assert!(!refused.is_outage(), "{:?}", refused);
+        }
+        assert!(!ApiError::Conflict { id: None }.is_outage());
+    }
+
+    #[test]
+    fn pins_validation_errors_to_fields() {
+        assert_eq!(ApiError::TooLong.field(), Some(Field::DisplayName));
+        assert_eq!(ApiError::TooManyLinks.field(), Some(Field::Body));
+        assert_eq!(
+            ApiError::from_response(400, r#"{"error":"invalid_input","message":"missing selectors"}"#, None).field(),
+            Some(Field::Selection)
+        );
+        for message in ["missing parent_id", "post mismatch", "invalid json", "missing slug", "missing fields"] {
+            let body = format!(r#"{{"error":"invalid_input","message":"{}"}}"#, message);
+            assert_eq!(ApiError::from_response(400, &body, None).field(), None, "{}", message);
+        }
+        assert_eq!(ApiError::from_response(400, r#"{"error":"invalid_input"}"#, None).field(), None);
+        assert_eq!(ApiError::RateLimited { retry_after: None }.field(), None);
+        assert!(ApiError::RateLimited { retry_after: None }.message(Lang::En).contains("15 seconds"));
+    }
+}
@@ -0,0 +1,231 @@
+//! Text fragment links (`#:~:text=`) and `#anno-<id>` permalinks.
+//!
+//! Directives follow the Text Fragments spec: long quotes are shortened to
+//! `textStart,textEnd`, and `prefix-,` / `,-suffix` context is added when the
+//! quoted text occurs more than once in the article.
+
+/// Longer quotes are linked by their first and last words only.
+const MAX_EXACT_CHARS: usize = 80;
+const EDGE_WORDS: usize = 4;
+// Articles are largely Japanese, which has no spaces to split words on.
+const EDGE_CHARS: usize = 12;
+const CONTEXT_WORDS: usize = 3;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextDirective {
+    pub prefix: Option<String>,
+    pub start: String,
+    pub end: Option<String>,
+    pub suffix: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FragmentTarget {
+    Text(TextDirective),
+    Annotation(i64),
+}
+
+pub fn annotation_hash(id: i64) -> String {
+    format!("#anno-{}", id)
+}
+
+fn has_spaces(s: &str) -> bool {
+    s.split_whitespace().nth(1).is_some()
+}
+
+fn leading(s: &str) -> String {
+    if has_spaces(s) {
+        s.split_whitespace().take(EDGE_WORDS).collect::<Vec<_>>().join(" ")
+    } else {
+        s.chars().take(EDGE_CHARS).collect()
+    }
+}
+
+fn trailing(s: &str, words: usize, chars: usize) -> String {
+    if has_spaces(s) {
+        let all: Vec<&str> = s.split_whitespace().collect();
+        all[all.len().saturating_sub(words)..].join(" ")
+    } else {
+        let all: Vec<char> = s.chars().collect();
+        all[all.len().saturating_sub(chars)..].iter().collect()
+    }
+}
+
+fn context_after(s: &str) -> String {
+    if has_spaces(s) {
+        s.split_whitespace().take(CONTEXT_WORDS).collect::<Vec<_>>().join(" ")
+    } else {
+        s.trim().chars().take(EDGE_CHARS).collect()
+    }
+}
+
+fn slice(chars: &[char], start: usize, end: usize) -> String {
+    chars[start.min(chars.len())..end.min(chars.len())].iter().collect()
+}
+
+impl TextDirective {
+    /// Directive for the codepoint range `start..end` of `plain`.
+    pub fn for_range(plain: &str, start: usize, end: usize) -> TextDirective {
+        let chars: Vec<char> = plain.chars().collect();
+        let exact = slice(&chars, start, end);
+        let exact = exact.trim();
+        let (text_start, text_end) = if exact.chars().count() > MAX_EXACT_CHARS {
+            (leading(exact), Some(trailing(exact, EDGE_WORDS, EDGE_CHARS)))
+        } else {
+            (exact.to_string(), None)
+        };
+        let mut directive = TextDirective { prefix: None, start: text_start, end: text_end, suffix: None };
+        if plain.matches(directive.start.as_str()).nth(1).is_some() {
+            let before = slice(&chars, 0, start);
+            let after = slice(&chars, end, chars.len());
+            let prefix = trailing(before.trim_end(), CONTEXT_WORDS, EDGE_CHARS);
+            let suffix = context_after(after.trim_start());
+            directive.prefix = (!prefix.is_empty()).then_some(prefix);
+            directive.suffix = (!suffix.is_empty()).then_some(suffix);
+        }
+        directive
+    }
+
+    /// The `:~:text=` directive, percent-encoded.
+    pub fn to_fragment(&self) -> String {
+        let mut parts = vec![];
+        if let Some(p) = &self.prefix {
+            parts.push(format!("{}-", encode(p)));
+        }
+        parts.push(encode(&self.start));
+        if let Some(e) = &self.end {
+            parts.push(encode(e));
+        }
+        if let Some(s) = &self.suffix {
+            parts.push(format!("-{}", encode(s)));
+        }
+        format!(":~:text={}", parts.join(","))
+    }
+
+    fn parse(value: &str) -> Option<TextDirective> {
+        let mut parts: Vec<&str> = value.split(',').collect();
+        let prefix = match parts.first().copied() {
+            Some(p) if parts.len() > 1 && p.ends_with('-') => {
+                parts.remove(0);
+                Some(decode(&p[..p.len() - 1])?)
+            }
+            _ => None,
+        };
+        let suffix = match parts.last().copied() {
+            Some(s) if parts.len() > 1 && s.starts_with('-') => {
+                parts.pop();
+                Some(decode(&s[1..])?)
+            }
+            _ => None,
+        };
+        let (start, end) = match parts.as_slice() {
+            [start] => (decode(start)?, None),
+            [start, end] => (decode(start)?, Some(decode(end)?)),
+            _ => return None,
+        };
+        (!start.is_empty()).then_some(TextDirective { prefix, start, end, suffix })
+    }
+
+    /// Codepoint range in `plain` this directive points at.
+    pub fn resolve(&self, plain: &str) -> Option<(usize, usize)> {
+        let mut from = 0;
+        while let Some(found) = plain[from..].find(&self.start) {
+            let start = from + found;
+            let after_start = start + self.start.len();
+            from = start + self.start.chars().next().map_or(1, char::len_utf8);
+            let prefix_ok = self.prefix.as_ref().is_none_or(|p| plain[..start].trim_end().ends_with(p.as_str()));
+            if !prefix_ok {
+                continue;
+            }
+            let end = match &self.end {
+                None => after_start,
+                Some(e) => match plain[after_start..].find(e.as_str()) {
+                    Some(i) => after_start + i + e.len(),
+                    None => return None,
+                },
+            };
+            let suffix_ok = self.suffix.as_ref().is_none_or(|s| plain[end..].trim_start().starts_with(s.as_str()));
+            if suffix_ok {
+                return Some((plain[..start].chars().count(), plain[..end].chars().count()));
+            }
+        }
+        None
+    }
+}
+
+/// Parse a URL hash (with or without the leading `#`).
+pub fn parse_hash(hash: &str) -> Option<FragmentTarget> {
+    let hash = hash.trim_start_matches('#');
+    if let Some((_, directives)) = hash.split_once(":~:") {
+        return directives
+            .split('&')
+            .find_map(|d| d.strip_prefix("text="))
+            .and_then(TextDirective::parse)
+            .map(FragmentTarget::Text);
+    }
+    hash.strip_prefix("anno-")?.parse().ok().map(FragmentTarget::Annotation)
+}
+
+// Percent-encode everything except unreserved characters; `-`, `,` and `&`
+// are syntax inside a text directive so they are always escaped.
+fn encode(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"_.!~*'()".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+fn decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_phrase_gets_context_and_resolves_back() {
+        let plain = "猫が好き。犬も好き。猫が好き。";
+        let d = TextDirective::for_range(plain, 10, 14);
+        assert_eq!(d.start, "猫が好き");
+        assert_eq!(d.prefix.as_deref(), Some("猫が好き。犬も好き。"));
+        assert_eq!(d.resolve(plain), Some((10, 14)));
+        let parsed = parse_hash(&format!("#{}", d.to_fragment()));
+        assert_eq!(parsed, Some(FragmentTarget::Text(d)));
+    }
+
+    #[test]
+    fn long_selection_uses_start_and_end() {
+        let plain = "one two three four five six seven eight nine ten eleven twelve thirteen fourteen fifteen sixteen seventeen";
+        let d = TextDirective::for_range(plain, 0, plain.chars().count());
+        assert_eq!(d.start, "one two three four");
+        assert_eq!(d.end.as_deref(), Some("fourteen fifteen sixteen seventeen"));
+        assert_eq!(d.resolve(plain), Some((0, plain.chars().count())));
+    }
+
+    #[test]
+    fn encodes_directive_syntax() {
+        let d = TextDirective { prefix: Some("a-b".into()), start: "x,y".into(), end: None, suffix: Some("&z".into()) };
+        assert_eq!(d.to_fragment(), ":~:text=a%2Db-,x%2Cy,-%26z");
+        assert_eq!(parse_hash("#anno-42"), Some(FragmentTarget::Annotation(42)));
+        assert_eq!(parse_hash("#intro"), None);
+    }
+}
@@ -0,0 +1,211 @@
+//! `<mark>`-based highlighter.
+//!
+//! A range is split into one segment per intersected text node and each
+//! segment is wrapped on its own, so highlights may cross `<p>`, `<em>` or
+//! link boundaries. Overlapping annotations nest their marks; every mark
+//! carries `data-depth` (how many highlights cover it) for the intensity
+//! styles and `data-anno-id` so one annotation can be removed again.
+//!
+//! Search matches are wrapped the same way but tagged `data-search-hit`
+//! instead, so they neither count towards the depth of annotation marks
+//! nor stand in for the annotation under the pointer.
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{window, Element, Node, Range, ScrollBehavior, ScrollIntoViewOptions, ScrollLogicalPosition, Text};
+
+use crate::dom_text;
+
+const ID_ATTR: &str = "data-anno-id";
+const DEPTH_ATTR: &str = "data-depth";
+const FOCUS_CLASS: &str = "anno--fx";
+const FLASH_CLASS: &str = "anno--flash";
+const SEARCH_ATTR: &str = "data-search-hit";
+const SEARCH_CLASS: &str = "search-hit";
+const SEARCH_CURRENT_CLASS: &str = "search-hit--current";
+
+fn segments(range: &Range) -> Vec<(Text, u32, u32)> {
+    let Ok(common) = range.common_ancestor_container() else { return vec![] };
+    let (Ok(start_node), Ok(start_offset)) = (range.start_container(), range.start_offset()) else { return vec![] };
+    let (Ok(end_node), Ok(end_offset)) = (range.end_container(), range.end_offset()) else { return vec![] };
+    let nodes = match common.dyn_ref::<Text>() {
+        Some(text) => vec![text.clone()],
+        None => dom_text::text_nodes(&common),
+    };
+    nodes
+        .into_iter()
+        .filter(|t| range.intersects_node(t).unwrap_or(false))
+        .filter_map(|t| {
+            let node: &Node = t.as_ref();
+            let from = if *node == start_node { start_offset } else { 0 };
+            let to = if *node == end_node { end_offset } else { t.length() };
+            // whitespace-only segments sit between block elements; wrapping them breaks layout
+            let covered = t.substring_data(from, to.saturating_sub(from)).unwrap_or_default();
+            (from < to && !covered.trim().is_empty()).then_some((t, from, to))
+        })
+        .collect()
+}
+
+fn depth_of(mark: &Element) -> usize {
+    let mut depth = 1;
+    let mut parent = mark.parent_element();
+    while let Some(el) = parent {
+        if el.has_attribute(ID_ATTR) {
+            depth += 1;
+        }
+        parent = el.parent_element();
+    }
+    depth
+}
+
+/// Wrap every text segment of `range` in a `<mark class=class_name>` tagged
+/// with `id`. Returns the created marks in document order.
+pub fn highlight_range(range: &Range, id: &str, class_name: &str) -> Vec<Element> {
+    let marks = wrap_range(range, class_name, ID_ATTR, id);
+    for mark in &marks {
+        let _ = mark.set_attribute(DEPTH_ATTR, &depth_of(mark).to_string());
+    }
+    marks
+}
+
+/// Mark search match number `n`.
+pub fn mark_search_hit(range: &Range, n: usize) -> Vec<Element> {
+    wrap_range(range, SEARCH_CLASS, SEARCH_ATTR, &n.to_string())
+}
+
+fn wrap_range(range: &Range, class_name: &str, attr: &str, value: &str) -> Vec<Element> {
+    let Some(doc) = window().and_then(|w| w.document()) else { return vec![] };
+    let mut marks = vec![];
+    for (text, from, to) in segments(range) {
+        let mut node = text;
+        if to < node.length() {
+            let _ = node.split_text(to);
+        }
+        if from > 0 {
+            match node.split_text(from) {
+                Ok(tail) => node = tail,
+                Err(_) => continue,
+            }
+        }
+        let (Some(parent), Ok(mark)) = (node.parent_node(), doc.create_element("mark")) else { continue };
+        mark.set_class_name(class_name);
+        let _ = mark.set_attribute(attr, value);
+        if parent.insert_before(&mark, Some(&node)).is_ok() && mark.append_child(&node).is_ok() {
+            marks.push(mark);
+        }
+    }
+    marks
+}
+
+/// Every mark belonging to annotation `id` below `root`.
+pub fn marks_for(root: &Element, id: &str) -> Vec<Element> {
+    query_marks(root, &format!("mark[{}=\"{}\"]", ID_ATTR, id))
+}
+
+fn query_marks(root: &Element, selector: &str) -> Vec<Element> {
+    let mut out = vec![];
+    if let Ok(list) = root.query_selector_all(selector) {
+        for i in 0..list.length() {
+            if let Some(el) = list.item(i).and_then(|n| n.dyn_into::<Element>().ok()) {
+                out.push(el);
+            }
+        }
+    }
+    out
+}
+
+/// Unwrap all marks of annotation `id`, merging the split text nodes back
+/// together and recomputing the depth of any highlights nested inside.
+pub fn remove_highlight(root: &Element, id: &str) {
+    unwrap_marks(marks_for(root, id));
+    if let Ok(list) = root.query_selector_all(&format!("mark[{}]", ID_ATTR)) {
+        for i in 0..list.length() {
+            if let Some(el) = list.item(i).and_then(|n| n.dyn_into::<Element>().ok()) {
+                let _ = el.set_attribute(DEPTH_ATTR, &depth_of(&el).to_string());
+            }
+        }
+    }
+}
+
+fn unwrap_marks(marks: Vec<Element>) {
+    for mark in marks {
+        let Some(parent) = mark.parent_node() else { continue };
+        while let Some(child) = mark.first_child() {
+            let _ = parent.insert_before(&child, Some(&mark));
+        }
+        let _ = parent.remove_child(&mark);
+        parent.normalize();
+    }
+}
+
+/// Unwrap every search match below `root`.
+pub fn clear_search_hits(root: &Element) {
+    unwrap_marks(query_marks(root, &format!("mark[{}]", SEARCH_ATTR)));
+}
+
+/// Single out search match `n` and scroll it into view, or clear the
+/// current match with `None`.
+pub fn show_search_hit(root: &Element, n: Option<usize>) {
+    for mark in query_marks(root, &format!("mark.{}", SEARCH_CURRENT_CLASS)) {
+        let _ = mark.class_list().remove_1(SEARCH_CURRENT_CLASS);
+    }
+    let Some(n) = n else { return };
+    let marks = query_marks(root, &format!("mark[{}=\"{}\"]", SEARCH_ATTR, n));
+    for mark in &marks {
+        let _ = mark.class_list().add_1(SEARCH_CURRENT_CLASS);
+    }
+    if let Some(first) = marks.first() {
+        first.scroll_into_view_with_scroll_into_view_options(&centered());
+    }
+}
+
+fn centered() -> ScrollIntoViewOptions {
+    let opts = ScrollIntoViewOptions::new();
+    opts.set_behavior(ScrollBehavior::Smooth);
+    opts.set_block(ScrollLogicalPosition::Center);
+    opts
+}
+
+/// Move the marks of `from` over to `to`, e.g. once the server assigns an
+/// id to a provisional annotation.
+pub fn relabel(root: &Element, from: &str, to: &str) {
+    for mark in marks_for(root, from) {
+        let _ = mark.set_attribute(ID_ATTR, to);
+    }
+}
+
+/// Annotation id of the innermost highlight containing `el`, if any.
+pub fn annotation_id_at(el: &Element) -> Option<String> {
+    el.closest(&format!("mark[{}]", ID_ATTR)).ok().flatten()?.get_attribute(ID_ATTR)
+}
+
+/// Mark the highlights of `id` as focused, clearing any previous focus.
+pub fn set_focused(root: &Element, id: Option<&str>) {
+    for mark in query_marks(root, &format!("mark.{}", FOCUS_CLASS)) {
+        let _ = mark.class_list().remove_1(FOCUS_CLASS);
+    }
+    if let Some(id) = id {
+        for mark in marks_for(root, id) {
+            let _ = mark.class_list().add_1(FOCUS_CLASS);
+        }
+    }
+}
+
+/// Scroll the first highlight of `id` into view and flash all of its marks.
+/// Returns `false` when the annotation has no highlight in `root`.
+pub fn reveal(root: &Element, id: &str) -> bool {
+    let marks = marks_for(root, id);
+    let Some(first) = marks.first() else { return false };
+    first.scroll_into_view_with_scroll_into_view_options(&centered());
+    for mark in &marks {
+        let _ = mark.class_list().add_1(FLASH_CLASS);
+    }
+    if let Some(w) = window() {
+        let cb = Closure::once_into_js(Box::new(move || {
+            for mark in &marks {
+                let _ = mark.class_list().remove_1(FLASH_CLASS);
+            }
+        }) as Box<dyn FnOnce()>);
+        let _ = w.set_timeout_with_callback_and_timeout_and_arguments_0(cb.as_ref().unchecked_ref(), 1200);
+    }
+    true
+}
@@ -0,0 +1,87 @@
+//! Annotation kinds, mirroring `ANNOTATION_KINDS` on the server.
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase", from = "String")]
+pub enum AnnotationKind {
+    #[default]
+    Comment,
+    Question,
+    Citation,
+    Critique,
+    Praise,
+}
+
+impl AnnotationKind {
+    pub const ALL: [AnnotationKind; 5] = [
+        AnnotationKind::Comment,
+        AnnotationKind::Question,
+        AnnotationKind::Citation,
+        AnnotationKind::Critique,
+        AnnotationKind::Praise,
+    ];
+
+    /// The stored name, also used in CSS classes.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AnnotationKind::Comment => "comment",
+            AnnotationKind::Question => "question",
+            AnnotationKind::Citation => "citation",
+            AnnotationKind::Critique => "critique",
+            AnnotationKind::Praise => "praise",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AnnotationKind::Comment => "Comment",
+            AnnotationKind::Question => "Question",
+            AnnotationKind::Citation => "Citation",
+            AnnotationKind::Critique => "Critique",
+            AnnotationKind::Praise => "Praise",
+        }
+    }
+
+    /// Parse a `<select>` value; anything unknown is a plain comment.
+    pub fn parse(value: &str) -> AnnotationKind {
+        AnnotationKind::ALL.into_iter().find(|k| k.as_str() == value).unwrap_or_default()
+    }
+
+    /// Classes for this kind's highlight marks.
+    pub fn mark_class(self) -> String {
+        format!("anno anno--{}", self.as_str())
+    }
+
+    pub fn badge_class(self) -> String {
+        format!("badge-kind badge-{}", self.as_str())
+    }
+}
+
+// unknown kinds fall back like `normalizeAnnotationKind` does
+impl From<String> for AnnotationKind {
+    fn from(value: String) -> Self {
+        AnnotationKind::parse(&value)
+    }
+}
+
+impl fmt::Display for AnnotationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_and_missing_kinds_are_comments() {
+        assert_eq!(serde_json::from_str::<AnnotationKind>("\"question\"").unwrap(), AnnotationKind::Question);
+        assert_eq!(serde_json::from_str::<AnnotationKind>("\"rant\"").unwrap(), AnnotationKind::Comment);
+        assert_eq!(AnnotationKind::parse("praise"), AnnotationKind::Praise);
+        assert_eq!(AnnotationKind::parse(""), AnnotationKind::Comment);
+        assert_eq!(serde_json::to_string(&AnnotationKind::Citation).unwrap(), "\"citation\"");
+    }
+}
@@ -1,4 +1,28 @@
+mod anchoring;
+mod annotation;
+pub mod api;
 mod app;
+mod botcheck;
+mod dom_text;
+mod draft;
+mod error;
+mod fragment;
+mod highlight;
+mod kind;
+mod live;
+mod markdown;
+mod offset;
+mod outbox;
+mod paging;
+mod report;
+mod retry;
+mod sanitize;
+mod search;
+mod selection;
+mod selector;
+mod sort;
+mod store;
+mod thread;
 use app::App;
 use leptos::*;
 use wasm_bindgen::prelude::*;
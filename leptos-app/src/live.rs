@@ -0,0 +1,65 @@
+//! Live updates by polling `/api/annotations/list` for rows newer than the
+//! latest one seen.
+//!
+//! The server has no event stream, so the sidebar polls on an interval
+//! that backs off while requests fail and pauses while the tab is hidden
+//! or offline. `created_at` only has second precision, so each poll asks
+//! for rows from the second before the latest one and relies on the store
+//! merging repeats by id.
+use std::time::Duration;
+
+use web_sys::window;
+
+use crate::annotation::Annotation;
+
+pub const POLL_INTERVAL: Duration = Duration::from_secs(20);
+const MAX_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Wait before the next poll after `failures` failed ones in a row.
+pub fn interval(failures: u32) -> Duration {
+    POLL_INTERVAL.saturating_mul(2u32.saturating_pow(failures)).min(MAX_INTERVAL)
+}
+
+/// Move `latest` forward to the newest `created_at` in `page`.
+pub fn advance(latest: &mut Option<String>, page: &[Annotation]) {
+    if let Some(newest) = page.iter().filter_map(|a| a.created_at.as_deref()).max() {
+        if latest.as_deref().is_none_or(|l| newest > l) {
+            *latest = Some(newest.to_string());
+        }
+    }
+}
+
+/// The `after` cursor for a poll: one second before `latest`.
+pub fn poll_after(latest: &str) -> String {
+    let at = js_sys::Date::parse(&format!("{}Z", latest.replace(' ', "T")));
+    if at.is_nan() {
+        return latest.to_string();
+    }
+    let before = js_sys::Date::new(&(at - 1000.0).into());
+    String::from(before.to_iso_string()).replace('T', " ").chars().take(19).collect()
+}
+
+/// Polling is pointless while nobody is looking or the network is down.
+pub fn should_poll() -> bool {
+    let Some(win) = window() else { return false };
+    let hidden = win.document().is_some_and(|d| d.hidden());
+    !hidden && win.navigator().on_line()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotation::fixture;
+
+    #[test]
+    fn advances_to_the_newest_row_and_backs_off() {
+        let row = |created_at: &str| fixture(1, None, created_at);
+        let mut latest = None;
+        advance(&mut latest, &[row("2024-05-01 10:00:02"), row("2024-05-01 10:00:01")]);
+        assert_eq!(latest.as_deref(), Some("2024-05-01 10:00:02"));
+        advance(&mut latest, &[row("2024-05-01 09:00:00")]);
+        assert_eq!(latest.as_deref(), Some("2024-05-01 10:00:02"));
+        assert_eq!(interval(0), POLL_INTERVAL);
+        assert_eq!(interval(10), MAX_INTERVAL);
+    }
+}
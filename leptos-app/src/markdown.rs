@@ -0,0 +1,220 @@
+//! The composer's Markdown dialect: `**bold**`, `*italic*`, `` `code` ``,
+//! autolinked `http(s)://` URLs and line breaks.
+//!
+//! Source compiles to the [`Inline`] tree from [`crate::sanitize`], so the
+//! output only ever contains tags the server sanitizer keeps and the live
+//! preview renders exactly what will be stored.
+use crate::sanitize::Inline;
+
+/// Mirrors `MODERATION_URL_THRESHOLD` in `src/lib/annotations/constants.ts`.
+pub const MODERATION_URL_THRESHOLD: usize = 3;
+
+/// Characters trimmed from the end of an autolinked URL.
+const URL_TRAILING: &[char] = &['.', ',', ';', ':', '!', '?', ')', '\'', '"', '。', '、', '）', '」', '』'];
+
+pub fn parse(src: &str) -> Vec<Inline> {
+    let chars: Vec<char> = src.replace("\r\n", "\n").chars().collect();
+    parse_span(&chars)
+}
+
+/// Compile `src` to the `body_html` sent to the server.
+pub fn to_html(src: &str) -> String {
+    let mut out = String::new();
+    write_html(&parse(src), &mut out);
+    out
+}
+
+/// URLs in `html` as the server's moderation check counts them.
+pub fn url_count(html: &str) -> usize {
+    let lower = html.to_ascii_lowercase();
+    lower.matches("http://").count() + lower.matches("https://").count()
+}
+
+fn parse_span(chars: &[char]) -> Vec<Inline> {
+    let mut out = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let rest = &chars[i..];
+        if c == '\\' && rest.get(1).is_some_and(|n| n.is_ascii_punctuation()) {
+            push_text(&mut out, rest[1]);
+            i += 2;
+        } else if c == '`' {
+            match rest[1..].iter().position(|&d| d == '`') {
+                Some(len) if len > 0 => {
+                    out.push(Inline::Code(vec![Inline::Text(rest[1..=len].iter().collect())]));
+                    i += len + 2;
+                }
+                _ => {
+                    push_text(&mut out, c);
+                    i += 1;
+                }
+            }
+        } else if c == '\n' {
+            out.push(Inline::Break);
+            i += 1;
+        } else if (c == '*' || c == '_') && rest.get(1) == Some(&c) {
+            match find_close(chars, i, 2) {
+                Some(end) => {
+                    out.push(Inline::Strong(parse_span(&chars[i + 2..end])));
+                    i = end + 2;
+                }
+                None => {
+                    push_text(&mut out, c);
+                    push_text(&mut out, c);
+                    i += 2;
+                }
+            }
+        } else if c == '*' || c == '_' {
+            match find_close(chars, i, 1) {
+                Some(end) => {
+                    out.push(Inline::Em(parse_span(&chars[i + 1..end])));
+                    i = end + 1;
+                }
+                None => {
+                    push_text(&mut out, c);
+                    i += 1;
+                }
+            }
+        } else if let Some(len) = url_at(chars, i) {
+            let href: String = rest[..len].iter().collect();
+            // the scheme is left out of the link text so each link counts
+            // once towards the moderation threshold
+            let label = href.split_once("://").map_or(href.as_str(), |(_, l)| l).to_string();
+            out.push(Inline::Link { href, children: vec![Inline::Text(label)] });
+            i += len;
+        } else {
+            push_text(&mut out, c);
+            i += 1;
+        }
+    }
+    out
+}
+
+fn push_text(out: &mut Vec<Inline>, c: char) {
+    match out.last_mut() {
+        Some(Inline::Text(t)) => t.push(c),
+        _ => out.push(Inline::Text(c.to_string())),
+    }
+}
+
+// Index of the delimiter closing the run of `width` `*`/`_` at `open`.
+fn find_close(chars: &[char], open: usize, width: usize) -> Option<usize> {
+    let d = chars[open];
+    let start = open + width;
+    if chars.get(start).is_none_or(|c| c.is_whitespace()) {
+        return None;
+    }
+    // `snake_case_words` are not emphasis
+    if d == '_' && open > 0 && chars[open - 1].is_alphanumeric() {
+        return None;
+    }
+    let mut j = start + 1;
+    while j < chars.len() {
+        if chars[j] != d || chars[j - 1] == '\\' {
+            j += 1;
+            continue;
+        }
+        let run = chars[j..].iter().take_while(|&&c| c == d).count();
+        // a `***` run closes both an outer and an inner span; the outer
+        // one takes its last characters
+        if (run == width || run >= 3) && !chars[j - 1].is_whitespace() {
+            let close = j + run - width;
+            let boundary_ok = d != '_' || chars.get(j + run).is_none_or(|c| !c.is_alphanumeric());
+            if boundary_ok {
+                return Some(close);
+            }
+        }
+        j += run;
+    }
+    None
+}
+
+// Length of the URL starting at `i`, if one does.
+fn url_at(chars: &[char], i: usize) -> Option<usize> {
+    if i > 0 && chars[i - 1].is_alphanumeric() {
+        return None;
+    }
+    let head: String = chars[i..chars.len().min(i + 8)].iter().collect::<String>().to_ascii_lowercase();
+    let scheme = ["https://", "http://"].into_iter().find(|s| head.starts_with(s))?;
+    let mut len = chars[i..].iter().position(|c| c.is_whitespace() || matches!(c, '<' | '>' | '"' | '`')).unwrap_or(chars.len() - i);
+    while len > scheme.len() && URL_TRAILING.contains(&chars[i + len - 1]) {
+        // keep a closing paren that balances one inside the URL
+        if chars[i + len - 1] == ')' && chars[i..i + len].iter().filter(|&&c| c == '(').count() >= chars[i..i + len].iter().filter(|&&c| c == ')').count() {
+            break;
+        }
+        len -= 1;
+    }
+    (len > scheme.len()).then_some(len)
+}
+
+fn escape(s: &str, out: &mut String) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn write_html(nodes: &[Inline], out: &mut String) {
+    for node in nodes {
+        let (tag, children) = match node {
+            Inline::Text(t) => {
+                escape(t, out);
+                continue;
+            }
+            Inline::Break => {
+                out.push_str("<br>");
+                continue;
+            }
+            Inline::Link { href, children } => {
+                out.push_str("<a href=\"");
+                escape(href, out);
+                out.push_str("\">");
+                write_html(children, out);
+                out.push_str("</a>");
+                continue;
+            }
+            Inline::Strong(c) => ("strong", c),
+            Inline::Em(c) => ("em", c),
+            Inline::Code(c) => ("code", c),
+        };
+        out.push_str(&format!("<{}>", tag));
+        write_html(children, out);
+        out.push_str(&format!("</{}>", tag));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sanitize;
+
+    #[test]
+    fn compiles_the_dialect() {
+        assert_eq!(
+            to_html("**太字** and *it* with `a<b>`\nsee https://ex.test/x?a=1&b=2."),
+            "<strong>太字</strong> and <em>it</em> with <code>a&lt;b&gt;</code><br>see <a href=\"https://ex.test/x?a=1&amp;b=2\">ex.test/x?a=1&amp;b=2</a>."
+        );
+        assert_eq!(to_html("snake_case_name and 2 * 3 * 4"), "snake_case_name and 2 * 3 * 4");
+        assert_eq!(to_html(r"\*not\* <script>"), "*not* &lt;script&gt;");
+        assert_eq!(to_html("**bold *and it***"), "<strong>bold <em>and it</em></strong>");
+    }
+
+    #[test]
+    fn output_survives_the_sanitizer_unchanged() {
+        let src = "**b** _e_ `c` https://a.test/(x)\nnext";
+        assert_eq!(sanitize::parse(&to_html(src)), parse(src));
+    }
+
+    #[test]
+    fn counts_links_like_the_server() {
+        let html = to_html("https://a.test http://b.test HTTPS://c.test");
+        assert_eq!(url_count(&html), 3);
+        assert!(url_count(&to_html("https://a.test https://b.test https://c.test https://d.test")) > MODERATION_URL_THRESHOLD);
+    }
+}
@@ -0,0 +1,135 @@
+//! Text offsets that carry their unit.
+//!
+//! Rust strings index by byte, the DOM and JavaScript by UTF-16 code unit,
+//! and stored `TextPositionSelector`s by codepoint. Mixing them silently
+//! corrupts positions in Japanese text and anything with emoji, so offsets
+//! crossing those boundaries go through [`TextOffset`].
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum TextUnit {
+    /// Unicode scalar values (`char`s); the unit stored selectors use.
+    #[default]
+    Codepoint,
+    /// UTF-16 code units, as used by DOM ranges and JavaScript strings.
+    Utf16,
+    /// UTF-8 bytes, as used by Rust `str` indexing.
+    Byte,
+}
+
+impl fmt::Display for TextUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TextUnit::Codepoint => "codepoint",
+            TextUnit::Utf16 => "utf16",
+            TextUnit::Byte => "byte",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextOffset {
+    pub value: usize,
+    pub unit: TextUnit,
+}
+
+impl TextOffset {
+    pub fn new(value: usize, unit: TextUnit) -> Self {
+        TextOffset { value, unit }
+    }
+
+    pub fn codepoint(value: usize) -> Self {
+        Self::new(value, TextUnit::Codepoint)
+    }
+
+    pub fn utf16(value: usize) -> Self {
+        Self::new(value, TextUnit::Utf16)
+    }
+
+    /// Byte index of this offset in `text`, or `None` when it lies past the
+    /// end or inside a character.
+    pub fn to_byte_index(self, text: &str) -> Option<usize> {
+        match self.unit {
+            TextUnit::Byte => text.is_char_boundary(self.value).then_some(self.value),
+            TextUnit::Codepoint => {
+                text.char_indices().map(|(i, _)| i).chain(std::iter::once(text.len())).nth(self.value)
+            }
+            TextUnit::Utf16 => {
+                let mut units = 0;
+                for (i, c) in text.char_indices() {
+                    if units == self.value {
+                        return Some(i);
+                    }
+                    if units > self.value {
+                        return None;
+                    }
+                    units += c.len_utf16();
+                }
+                (units == self.value).then_some(text.len())
+            }
+        }
+    }
+
+    /// The same position in `text` expressed in `unit`.
+    pub fn to_unit(self, text: &str, unit: TextUnit) -> Option<TextOffset> {
+        let byte = self.to_byte_index(text)?;
+        if self.unit == unit {
+            return Some(self);
+        }
+        let head = &text[..byte];
+        let value = match unit {
+            TextUnit::Byte => byte,
+            TextUnit::Codepoint => head.chars().count(),
+            TextUnit::Utf16 => head.encode_utf16().count(),
+        };
+        Some(TextOffset::new(value, unit))
+    }
+
+    /// Convert to codepoints, the unit used for anchoring.
+    pub fn to_codepoint(self, text: &str) -> Option<usize> {
+        self.to_unit(text, TextUnit::Codepoint).map(|o| o.value)
+    }
+}
+
+/// Length of `text` in `unit`.
+pub fn len_in(text: &str, unit: TextUnit) -> usize {
+    match unit {
+        TextUnit::Codepoint => text.chars().count(),
+        TextUnit::Utf16 => text.encode_utf16().count(),
+        TextUnit::Byte => text.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "猫a😀b";
+
+    #[test]
+    fn converts_between_units() {
+        let emoji_end = TextOffset::codepoint(3);
+        assert_eq!(emoji_end.to_unit(TEXT, TextUnit::Utf16), Some(TextOffset::utf16(4)));
+        assert_eq!(emoji_end.to_unit(TEXT, TextUnit::Byte), Some(TextOffset::new(8, TextUnit::Byte)));
+        assert_eq!(TextOffset::utf16(4).to_codepoint(TEXT), Some(3));
+        assert_eq!(TextOffset::new(4, TextUnit::Byte).to_codepoint(TEXT), Some(2));
+        assert_eq!(TextOffset::codepoint(4).to_byte_index(TEXT), Some(TEXT.len()));
+    }
+
+    #[test]
+    fn rejects_offsets_inside_a_character() {
+        assert_eq!(TextOffset::utf16(3).to_codepoint(TEXT), None);
+        assert_eq!(TextOffset::new(1, TextUnit::Byte).to_codepoint(TEXT), None);
+        assert_eq!(TextOffset::codepoint(5).to_byte_index(TEXT), None);
+    }
+
+    #[test]
+    fn units_serialize_as_stored_labels() {
+        assert_eq!(serde_json::to_string(&TextUnit::Utf16).unwrap(), "\"utf16\"");
+        assert_eq!(serde_json::from_str::<TextUnit>("\"codepoint\"").unwrap(), TextUnit::Codepoint);
+        assert_eq!(len_in(TEXT, TextUnit::Utf16), 5);
+    }
+}
@@ -0,0 +1,87 @@
+//! Annotations written without connectivity, kept in `localStorage` until
+//! they can be sent.
+//!
+//! Entries keep the idempotency key they were created with, so flushing
+//! one that did reach the server before the connection dropped does not
+//! store it twice. One the server refuses is parked rather than dropped,
+//! so the reader can still retry or discard what they wrote.
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{window, Event, Storage};
+
+use crate::annotation::NewAnnotation;
+
+const KEY: &str = "anno-outbox";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Queued {
+    pub new: NewAnnotation,
+    /// When the reader wrote it, in the server's `created_at` format.
+    pub created_at: String,
+    /// Refused for a reason waiting will not fix; kept, but skipped by
+    /// flushes until the reader retries or discards it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub parked: bool,
+}
+
+fn storage() -> Option<Storage> {
+    window()?.local_storage().ok().flatten()
+}
+
+/// Queued entries for every post, oldest first.
+pub fn load() -> Vec<Queued> {
+    storage()
+        .and_then(|s| s.get_item(KEY).ok().flatten())
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+fn save(entries: &[Queued]) {
+    let Some(store) = storage() else { return };
+    let _ = if entries.is_empty() {
+        store.remove_item(KEY)
+    } else {
+        store.set_item(KEY, &serde_json::to_string(entries).unwrap_or_default())
+    };
+}
+
+pub fn push(entry: Queued) {
+    let mut entries = load();
+    if !entries.iter().any(|e| e.new.idempotency_key == entry.new.idempotency_key) {
+        entries.push(entry);
+        save(&entries);
+    }
+}
+
+pub fn remove(idempotency_key: &str) {
+    let mut entries = load();
+    entries.retain(|e| e.new.idempotency_key != idempotency_key);
+    save(&entries);
+}
+
+pub fn set_parked(idempotency_key: &str, parked: bool) {
+    let mut entries = load();
+    if let Some(entry) = entries.iter_mut().find(|e| e.new.idempotency_key == idempotency_key) {
+        entry.parked = parked;
+        save(&entries);
+    }
+}
+
+/// Whether anything is still waiting to be sent; new annotations queue
+/// behind it so they reach the server in the order they were written.
+pub fn has_pending() -> bool {
+    load().iter().any(|e| !e.parked)
+}
+
+pub fn is_online() -> bool {
+    window().is_none_or(|w| w.navigator().on_line())
+}
+
+/// Call `on_online` whenever the browser regains connectivity.
+pub fn on_online(on_online: impl Fn() + 'static) {
+    let Some(win) = window() else { return };
+    let handler = Closure::wrap(Box::new(move |_: Event| on_online()) as Box<dyn FnMut(_)>);
+    win.add_event_listener_with_callback("online", handler.as_ref().unchecked_ref()).ok();
+    handler.forget();
+}
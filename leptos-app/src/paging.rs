@@ -0,0 +1,67 @@
+//! Cursor pagination of `/api/annotations/list`.
+//!
+//! The list is ordered by `created_at` and resumes strictly `after` a
+//! timestamp, which only has second precision. Rows sharing the last
+//! timestamp of a page could be cut off by the limit, so the next page
+//! starts before that timestamp and re-fetches them; the store merges
+//! duplicates by id.
+use js_sys::Array;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Element, IntersectionObserver, IntersectionObserverEntry, IntersectionObserverInit};
+
+use crate::annotation::Annotation;
+
+/// Annotations fetched per page.
+pub const PAGE_SIZE: u32 = 50;
+/// How far below the viewport the sentinel starts loading the next page.
+const PRELOAD_MARGIN: &str = "0px 0px 200px 0px";
+
+/// Whether a page of `len` rows may be followed by another.
+pub fn has_more(len: usize) -> bool {
+    len >= PAGE_SIZE as usize
+}
+
+/// The `after` cursor for the page following `page`.
+pub fn next_cursor(page: &[Annotation]) -> Option<String> {
+    let last = page.last()?.created_at.as_deref()?;
+    let earlier = page.iter().rev().filter_map(|a| a.created_at.as_deref()).find(|at| *at < last);
+    // a whole page from one second can only move past it
+    Some(earlier.unwrap_or(last).to_string())
+}
+
+/// Report whether `el` is within reach of the viewport, as it changes.
+pub fn observe(el: &Element, on_change: impl Fn(bool) + 'static) -> Option<IntersectionObserver> {
+    let callback = Closure::wrap(Box::new(move |entries: Array, _: JsValue| {
+        if let Some(entry) = entries.iter().last().and_then(|e| e.dyn_into::<IntersectionObserverEntry>().ok()) {
+            on_change(entry.is_intersecting());
+        }
+    }) as Box<dyn FnMut(Array, JsValue)>);
+    let init = IntersectionObserverInit::new();
+    init.set_root_margin(PRELOAD_MARGIN);
+    let observer = IntersectionObserver::new_with_options(callback.as_ref().unchecked_ref(), &init).ok()?;
+    callback.forget();
+    observer.observe(el);
+    Some(observer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotation::fixture;
+
+    fn at(id: i64, created_at: &str) -> Annotation {
+        fixture(id, None, created_at)
+    }
+
+    #[test]
+    fn cursor_backs_up_over_a_shared_last_second() {
+        let page = [at(1, "2024-05-01 10:00:00"), at(2, "2024-05-01 10:00:01"), at(3, "2024-05-01 10:00:01")];
+        assert_eq!(next_cursor(&page).as_deref(), Some("2024-05-01 10:00:00"));
+        let page = [at(1, "2024-05-01 10:00:00"), at(2, "2024-05-01 10:00:01")];
+        assert_eq!(next_cursor(&page).as_deref(), Some("2024-05-01 10:00:00"));
+        let page = [at(1, "2024-05-01 10:00:00"), at(2, "2024-05-01 10:00:00")];
+        assert_eq!(next_cursor(&page).as_deref(), Some("2024-05-01 10:00:00"));
+        assert_eq!(next_cursor(&[]), None);
+    }
+}
@@ -0,0 +1,89 @@
+//! Reporting annotations to the moderators.
+//!
+//! The server records a free-form `reason`; the dialog offers a fixed set
+//! and sends its code, followed by the reader's note when there is one.
+//! Reported ids are remembered in `localStorage` so the cards stay dimmed
+//! for this reader across visits.
+use std::collections::HashSet;
+
+use web_sys::{window, Storage};
+
+const KEY: &str = "anno-reported";
+/// Longest note sent along with a reason.
+pub const NOTE_MAX: usize = 500;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Reason {
+    #[default]
+    Spam,
+    Harassment,
+    Misinformation,
+    OffTopic,
+    /// Needs a note saying what is wrong.
+    Other,
+}
+
+impl Reason {
+    pub const ALL: [Reason; 5] = [Reason::Spam, Reason::Harassment, Reason::Misinformation, Reason::OffTopic, Reason::Other];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Reason::Spam => "spam",
+            Reason::Harassment => "harassment",
+            Reason::Misinformation => "misinformation",
+            Reason::OffTopic => "off_topic",
+            Reason::Other => "other",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Reason::Spam => "Spam or advertising",
+            Reason::Harassment => "Harassment or hate",
+            Reason::Misinformation => "Misleading or false",
+            Reason::OffTopic => "Off-topic",
+            Reason::Other => "Something else",
+        }
+    }
+
+    /// The `reason` sent to the server, or `None` when a required note is
+    /// missing.
+    pub fn with_note(self, note: &str) -> Option<String> {
+        let note: String = note.trim().chars().take(NOTE_MAX).collect();
+        match (self, note.is_empty()) {
+            (Reason::Other, true) => None,
+            (_, true) => Some(self.as_str().to_string()),
+            (_, false) => Some(format!("{}: {}", self.as_str(), note)),
+        }
+    }
+}
+
+fn storage() -> Option<Storage> {
+    window()?.local_storage().ok().flatten()
+}
+
+/// Annotations this reader has reported.
+pub fn load() -> HashSet<i64> {
+    storage()
+        .and_then(|s| s.get_item(KEY).ok().flatten())
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(reported: &HashSet<i64>) {
+    let (Some(store), Ok(raw)) = (storage(), serde_json::to_string(reported)) else { return };
+    let _ = store.set_item(KEY, &raw);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_the_reason() {
+        assert_eq!(Reason::Spam.with_note("  ").as_deref(), Some("spam"));
+        assert_eq!(Reason::OffTopic.with_note(" about cats ").as_deref(), Some("off_topic: about cats"));
+        assert_eq!(Reason::Other.with_note(""), None);
+        assert_eq!(Reason::Other.with_note(&"x".repeat(600)).map(|r| r.len()), Some("other: ".len() + NOTE_MAX));
+    }
+}
@@ -0,0 +1,77 @@
+//! Idempotency keys and backoff for retrying submissions.
+//!
+//! A key is generated once per annotation and sent with every attempt, so
+//! the server can recognise a retry of a request that already went through
+//! and answer `409 conflict` with the existing id instead of storing a
+//! duplicate.
+use std::time::Duration;
+
+use js_sys::{Promise, Uint8Array};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::window;
+
+/// Attempts per submission, including the first.
+pub const MAX_ATTEMPTS: u32 = 4;
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(8);
+/// Longest server-requested wait (`Retry-After`) sat out automatically.
+pub const MAX_WAIT: Duration = Duration::from_secs(30);
+
+/// Random UUID v4 from the Web Crypto API.
+///
+/// Fails rather than falling back to a predictable source: two readers
+/// drawing the same key would have one annotation silently dropped as a
+/// retry of the other.
+pub fn new_idempotency_key() -> Result<String, String> {
+    let crypto = window().and_then(|w| w.crypto().ok()).ok_or("Web Crypto is unavailable")?;
+    // `randomUUID` throws outside secure contexts; `getRandomValues` does not
+    if window().is_some_and(|w| w.is_secure_context()) && js_sys::Reflect::has(&crypto, &"randomUUID".into()).unwrap_or(false) {
+        return Ok(crypto.random_uuid());
+    }
+    let mut bytes = [0u8; 16];
+    let array = Uint8Array::new_with_length(16);
+    crypto
+        .get_random_values_with_array_buffer_view(&array)
+        .map_err(|_| "secure random numbers are unavailable".to_string())?;
+    array.copy_to(&mut bytes);
+    Ok(uuid_v4(bytes))
+}
+
+fn uuid_v4(mut bytes: [u8; 16]) -> String {
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// Delay before retry number `attempt` (0-based), doubling each time.
+pub fn backoff(attempt: u32) -> Duration {
+    BASE_DELAY.saturating_mul(2u32.saturating_pow(attempt)).min(MAX_DELAY)
+}
+
+pub async fn sleep(delay: Duration) {
+    let promise = Promise::new(&mut |resolve, _| {
+        if let Some(w) = window() {
+            let _ = w.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, delay.as_millis() as i32);
+        }
+    });
+    let _ = JsFuture::from(promise).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(backoff(0), Duration::from_millis(500));
+        assert_eq!(backoff(2), Duration::from_secs(2));
+        assert_eq!(backoff(10), MAX_DELAY);
+    }
+
+    #[test]
+    fn formats_random_bytes_as_uuid_v4() {
+        let uuid = uuid_v4([0xff; 16]);
+        assert_eq!(uuid, "ffffffff-ffff-4fff-bfff-ffffffffffff");
+    }
+}
@@ -0,0 +1,340 @@
+//! Annotation bodies parsed into the inline subset the server allows
+//! (`a` with http/https/mailto links, `strong`, `em`, `code`, `br`) and
+//! rendered as Leptos nodes.
+//!
+//! The server already runs `sanitizer.ts`; this is the second line of
+//! defence. Nothing is handed to `inner_html`: text becomes text nodes and
+//! only the elements below are ever created.
+use leptos::*;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Inline {
+    Text(String),
+    Strong(Vec<Inline>),
+    Em(Vec<Inline>),
+    Code(Vec<Inline>),
+    Link { href: String, children: Vec<Inline> },
+    Break,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Tag {
+    Strong,
+    Em,
+    Code,
+    A,
+}
+
+impl Tag {
+    fn from_name(name: &str) -> Option<Tag> {
+        match name {
+            "strong" => Some(Tag::Strong),
+            "em" => Some(Tag::Em),
+            "code" => Some(Tag::Code),
+            "a" => Some(Tag::A),
+            _ => None,
+        }
+    }
+}
+
+// An element still waiting for its closing tag. `href` is `None` for
+// links whose URL was rejected; their text is kept without the link.
+struct Open {
+    tag: Tag,
+    href: Option<String>,
+    children: Vec<Inline>,
+}
+
+impl Open {
+    fn close(self) -> Vec<Inline> {
+        match (self.tag, self.href) {
+            (Tag::Strong, _) => vec![Inline::Strong(self.children)],
+            (Tag::Em, _) => vec![Inline::Em(self.children)],
+            (Tag::Code, _) => vec![Inline::Code(self.children)],
+            (Tag::A, Some(href)) => vec![Inline::Link { href, children: self.children }],
+            (Tag::A, None) => self.children,
+        }
+    }
+}
+
+/// Elements whose content is dropped along with the tag.
+const DROP_CONTENT: &[&str] = &["script", "style", "template", "iframe", "object", "noscript", "textarea", "title"];
+
+pub fn is_safe_href(href: &str) -> bool {
+    let lower = href.trim().to_ascii_lowercase();
+    ["http:", "https:", "mailto:"].iter().any(|scheme| lower.starts_with(scheme))
+}
+
+/// Parse `html` into the allowed inline tree, dropping every other tag.
+pub fn parse(html: &str) -> Vec<Inline> {
+    let mut root: Vec<Inline> = vec![];
+    let mut stack: Vec<Open> = vec![];
+    let mut rest = html;
+    while !rest.is_empty() {
+        let Some(lt) = rest.find('<') else {
+            push_text(&mut stack, &mut root, rest);
+            break;
+        };
+        push_text(&mut stack, &mut root, &rest[..lt]);
+        rest = &rest[lt..];
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.find("-->").map_or("", |end| &after[end + 3..]);
+            continue;
+        }
+        let Some(end) = tag_end(rest) else {
+            // a stray `<` is just text
+            push_text(&mut stack, &mut root, "<");
+            rest = &rest[1..];
+            continue;
+        };
+        let raw = &rest[1..end];
+        rest = &rest[end + 1..];
+        let closing = raw.starts_with('/');
+        let raw = raw.trim_start_matches('/');
+        let name_len = raw.find(|c: char| c.is_whitespace() || c == '/').unwrap_or(raw.len());
+        let name = raw[..name_len].to_ascii_lowercase();
+        if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            continue;
+        }
+        if !closing && DROP_CONTENT.contains(&name.as_str()) {
+            rest = skip_past_close(rest, &name);
+            continue;
+        }
+        if name == "br" {
+            push(&mut stack, &mut root, Inline::Break);
+            continue;
+        }
+        let Some(tag) = Tag::from_name(&name) else { continue };
+        if closing {
+            // close the innermost matching element, and anything left open inside it
+            if let Some(pos) = stack.iter().rposition(|o| o.tag == tag) {
+                while stack.len() > pos {
+                    let open = stack.pop().expect("non-empty");
+                    extend(&mut stack, &mut root, open.close());
+                }
+            }
+        } else if tag == Tag::A && stack.iter().any(|o| o.tag == Tag::A) {
+            // links do not nest
+        } else {
+            let href = (tag == Tag::A)
+                .then(|| attr(&raw[name_len..], "href"))
+                .flatten()
+                .filter(|h| is_safe_href(h));
+            stack.push(Open { tag, href, children: vec![] });
+        }
+    }
+    while let Some(open) = stack.pop() {
+        extend(&mut stack, &mut root, open.close());
+    }
+    root
+}
+
+fn push(stack: &mut [Open], root: &mut Vec<Inline>, node: Inline) {
+    match stack.last_mut() {
+        Some(open) => open.children.push(node),
+        None => root.push(node),
+    }
+}
+
+fn extend(stack: &mut [Open], root: &mut Vec<Inline>, nodes: Vec<Inline>) {
+    for node in nodes {
+        match node {
+            Inline::Text(t) => push_text(stack, root, &t),
+            other => push(stack, root, other),
+        }
+    }
+}
+
+fn push_text(stack: &mut [Open], root: &mut Vec<Inline>, raw: &str) {
+    if raw.is_empty() {
+        return;
+    }
+    let text = decode_entities(raw);
+    let siblings = match stack.last_mut() {
+        Some(open) => &mut open.children,
+        None => root,
+    };
+    match siblings.last_mut() {
+        Some(Inline::Text(prev)) => prev.push_str(&text),
+        _ => siblings.push(Inline::Text(text)),
+    }
+}
+
+// Index of the `>` ending the tag at the start of `s`, skipping quoted
+// attribute values.
+fn tag_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in s.char_indices().skip(1) {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => return Some(i),
+            (None, '<') => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn skip_past_close<'a>(s: &'a str, name: &str) -> &'a str {
+    let lower = s.to_ascii_lowercase();
+    let close = format!("</{}", name);
+    match lower.find(&close) {
+        Some(i) => s[i..].find('>').map_or("", |j| &s[i + j + 1..]),
+        None => "",
+    }
+}
+
+// Value of attribute `name` in a tag's attribute text.
+fn attr(attrs: &str, name: &str) -> Option<String> {
+    let mut rest = attrs;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            return None;
+        }
+        let key_len = rest.find(|c: char| c.is_whitespace() || c == '=' || c == '/').unwrap_or(rest.len());
+        let key = rest[..key_len].to_ascii_lowercase();
+        rest = rest[key_len..].trim_start();
+        let value = if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (value, remaining) = match after.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let body = &after[1..];
+                    let end = body.find(q).unwrap_or(body.len());
+                    (&body[..end], body.get(end + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            rest = remaining;
+            value
+        } else {
+            ""
+        };
+        if key == name {
+            return Some(decode_entities(value));
+        }
+    }
+}
+
+/// Decode named and numeric character references.
+pub fn decode_entities(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest[1..].find(';').filter(|&semi| semi <= 10).and_then(|semi| {
+            let entity = &rest[1..semi + 1];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            }?;
+            Some((c, semi + 2))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+pub fn render_nodes(nodes: &[Inline]) -> View {
+    render_nodes_with(nodes, &|t| t.to_string().into_view())
+}
+
+/// Like [`render_nodes`], with text runs rendered by `text`, e.g. to mark
+/// search matches.
+pub fn render_nodes_with(nodes: &[Inline], text: &dyn Fn(&str) -> View) -> View {
+    nodes.iter().map(|node| render_node(node, text)).collect_view()
+}
+
+fn render_node(node: &Inline, text: &dyn Fn(&str) -> View) -> View {
+    match node {
+        Inline::Text(t) => text(t),
+        Inline::Strong(c) => view! { <strong>{render_nodes_with(c, text)}</strong> }.into_view(),
+        Inline::Em(c) => view! { <em>{render_nodes_with(c, text)}</em> }.into_view(),
+        Inline::Code(c) => view! { <code>{render_nodes_with(c, text)}</code> }.into_view(),
+        Inline::Break => view! { <br/> }.into_view(),
+        Inline::Link { href, children } => view! {
+            <a href=href.clone() rel="noopener nofollow ugc" target="_blank">{render_nodes_with(children, text)}</a>
+        }.into_view(),
+    }
+}
+
+/// The text runs of `nodes`, with line breaks as newlines.
+pub fn text_content(nodes: &[Inline]) -> String {
+    let mut out = String::new();
+    for node in nodes {
+        match node {
+            Inline::Text(t) => out.push_str(t),
+            Inline::Strong(c) | Inline::Em(c) | Inline::Code(c) | Inline::Link { children: c, .. } => out.push_str(&text_content(c)),
+            Inline::Break => out.push('\n'),
+        }
+    }
+    out
+}
+
+/// Render a stored `body_html` as safe Leptos nodes.
+pub fn render_body(html: &str) -> View {
+    render_nodes(&parse(html))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Inline {
+        Inline::Text(s.into())
+    }
+
+    #[test]
+    fn keeps_the_allowed_subset() {
+        assert_eq!(
+            parse(r#"a <STRONG class="x">b <em>c</em></strong><br/>d <code>&lt;e&gt;</code>"#),
+            vec![
+                text("a "),
+                Inline::Strong(vec![text("b "), Inline::Em(vec![text("c")])]),
+                Inline::Break,
+                text("d "),
+                Inline::Code(vec![text("<e>")]),
+            ]
+        );
+        assert_eq!(
+            parse(r#"<a href="https://x.test/?a=1&amp;b=2" onclick="evil()">x</a>"#),
+            vec![Inline::Link { href: "https://x.test/?a=1&b=2".into(), children: vec![text("x")] }]
+        );
+    }
+
+    #[test]
+    fn drops_unsafe_markup() {
+        assert_eq!(parse(r#"<a href="javascript:alert(1)">x</a>"#), vec![text("x")]);
+        assert_eq!(parse(r#"<a href="&#106;avascript:alert(1)">x</a>"#), vec![text("x")]);
+        assert_eq!(parse("<img src=x onerror=alert(1)>hi<script>alert(1)</script>!"), vec![text("hi!")]);
+        assert_eq!(parse("<div><p>p</p></div><!-- c -->"), vec![text("p")]);
+        assert_eq!(parse("1 < 2 <em>ok"), vec![text("1 < 2 "), Inline::Em(vec![text("ok")])]);
+        assert_eq!(parse("<strong><em>x</strong>y"), vec![Inline::Strong(vec![Inline::Em(vec![text("x")])]), text("y")]);
+    }
+}
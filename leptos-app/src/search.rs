@@ -0,0 +1,103 @@
+//! Client-side search over the article text and loaded annotations.
+//!
+//! Matching is a plain substring search over folded characters, so queries
+//! in languages written without spaces, such as Japanese, match anywhere.
+//! Folding is one character to one character (case, full-width ASCII,
+//! katakana to hiragana), which keeps match offsets valid in the original
+//! text.
+use leptos::*;
+
+use crate::annotation::Annotation;
+use crate::sanitize;
+
+fn fold(c: char) -> char {
+    let c = match c as u32 {
+        // full-width ASCII variants
+        0xFF01..=0xFF5E => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        0x3000 => ' ',
+        // katakana with a hiragana counterpart
+        0x30A1..=0x30F6 => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+        _ => c,
+    };
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+/// A folded query; empty when there is nothing to search for.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Query(Vec<char>);
+
+impl Query {
+    pub fn new(raw: &str) -> Query {
+        Query(raw.trim().chars().map(fold).collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Non-overlapping matches in `text`, as codepoint ranges.
+    pub fn find_all(&self, text: &str) -> Vec<(usize, usize)> {
+        let n = self.0.len();
+        if n == 0 {
+            return vec![];
+        }
+        let hay: Vec<char> = text.chars().map(fold).collect();
+        let mut out = vec![];
+        let mut i = 0;
+        while i + n <= hay.len() {
+            if hay[i..i + n] == self.0[..] {
+                out.push((i, i + n));
+                i += n;
+            } else {
+                i += 1;
+            }
+        }
+        out
+    }
+
+    /// Whether `a`'s body, quote or author contains the query.
+    pub fn matches(&self, a: &Annotation) -> bool {
+        let body = sanitize::text_content(&sanitize::parse(&a.body_html));
+        [body.as_str(), a.quote.as_deref().unwrap_or_default(), &a.author()]
+            .iter()
+            .any(|text| !self.find_all(text).is_empty())
+    }
+
+    /// `text` with every match wrapped in `<mark class="search-hit">`.
+    pub fn render(&self, text: &str) -> View {
+        let matches = self.find_all(text);
+        if matches.is_empty() {
+            return text.to_string().into_view();
+        }
+        let chars: Vec<char> = text.chars().collect();
+        let mut views = vec![];
+        let mut at = 0;
+        for (start, end) in matches {
+            views.push(chars[at..start].iter().collect::<String>().into_view());
+            let hit: String = chars[start..end].iter().collect();
+            views.push(view! { <mark class="search-hit">{hit}</mark> }.into_view());
+            at = end;
+        }
+        views.push(chars[at..].iter().collect::<String>().into_view());
+        views.collect_view()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_case_width_and_kana() {
+        let text = "選択→Add Commentで、右レールのＡＢＣ";
+        assert_eq!(Query::new("comment").find_all(text), vec![(7, 14)]);
+        assert_eq!(Query::new("れーる").find_all(text), vec![(17, 20)]);
+        assert_eq!(Query::new("abc").find_all(text), vec![(21, 24)]);
+        assert_eq!(Query::new("aa").find_all("aaaa"), vec![(0, 2), (2, 4)]);
+        assert!(Query::new("  ").find_all(text).is_empty());
+    }
+}
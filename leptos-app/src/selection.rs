@@ -0,0 +1,151 @@
+//! Reader selection inside the article, independent of the input device.
+//!
+//! `selectionchange` fires for mouse drags, Shift+arrow keyboard selection
+//! and touch handles alike, so the tracker debounces it instead of relying on
+//! `mouseup`. A keyboard shortcut opens the composer directly for the
+//! current selection, and so does a selection made by long-press once the
+//! finger is lifted, so the reader can still drag its handles first.
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use leptos::leptos_dom::helpers::TimeoutHandle;
+use leptos::set_timeout_with_handle;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{window, Event, KeyboardEvent};
+
+use crate::dom_text::{self, article_root};
+
+/// Selection settles this long after the last `selectionchange`.
+pub const SETTLE_DELAY: Duration = Duration::from_millis(250);
+/// Touch hold needed to count as a long-press, which starts a selection.
+pub const LONG_PRESS: Duration = Duration::from_millis(550);
+
+/// Selected text with its codepoint offsets in the article.
+pub fn current_selection() -> Option<(String, usize, usize)> {
+    let sel = window()?.get_selection().ok().flatten()?;
+    if sel.range_count() == 0 {
+        return None;
+    }
+    let range = sel.get_range_at(0).ok()?;
+    if range.collapsed() {
+        return None;
+    }
+    // offsets come from the live range, so repeated phrases anchor where the reader selected
+    let article = article_root()?;
+    let (start, end) = dom_text::range_offsets(&article, &range)?;
+    let text: String = dom_text::plain_text(&article).chars().skip(start).take(end - start).collect();
+    if text.trim().is_empty() {
+        return None;
+    }
+    Some((text, start, end))
+}
+
+pub fn selection_rect() -> Option<(f64, f64)> {
+    let win = window()?;
+    let sel = win.get_selection().ok().flatten()?;
+    if sel.range_count() == 0 { return None; }
+    let range = sel.get_range_at(0).ok()?;
+    if let Some(rects) = range.get_client_rects() {
+        if let Ok(val) = js_sys::Reflect::get(rects.as_ref(), &wasm_bindgen::JsValue::from_str("0")) {
+            if let Ok(rect) = val.dyn_into::<web_sys::DomRectReadOnly>() {
+                return Some((rect.right() + 6.0, rect.bottom() + 6.0));
+            }
+        }
+    }
+    None
+}
+
+pub fn collapse_selection() {
+    if let Some(sel) = window().and_then(|w| w.get_selection().ok().flatten()) {
+        let _ = sel.collapse_to_end();
+    }
+}
+
+/// Ctrl+Alt+M, the shortcut document editors commonly use for "add comment".
+pub fn is_compose_shortcut(e: &KeyboardEvent) -> bool {
+    e.ctrl_key() && e.alt_key() && !e.shift_key() && e.code() == "KeyM"
+}
+
+/// Call `on_settled` once the document selection has stopped changing for
+/// [`SETTLE_DELAY`].
+pub fn on_selection_settled(on_settled: impl Fn() + 'static) {
+    let Some(doc) = window().and_then(|w| w.document()) else { return };
+    let pending: Rc<RefCell<Option<TimeoutHandle>>> = Rc::new(RefCell::new(None));
+    let on_settled = Rc::new(on_settled);
+    let handler = Closure::wrap(Box::new(move |_: Event| {
+        if let Some(handle) = pending.borrow_mut().take() {
+            handle.clear();
+        }
+        let cb = on_settled.clone();
+        *pending.borrow_mut() = set_timeout_with_handle(move || cb(), SETTLE_DELAY).ok();
+    }) as Box<dyn FnMut(_)>);
+    doc.add_event_listener_with_callback("selectionchange", handler.as_ref().unchecked_ref()).ok();
+    handler.forget();
+}
+
+/// Call `on_select` when a selection started by a long-press in the
+/// article has settled with no finger down, and again each time its
+/// handles are dragged to a new settled selection. Once the selection is
+/// gone, ordinary taps are ignored until the next long-press.
+pub fn on_touch_selection(on_select: impl Fn() + 'static) {
+    let (Some(article), Some(doc)) = (article_root(), window().and_then(|w| w.document())) else { return };
+    let touching = Rc::new(Cell::new(false));
+    let touch_start = Rc::new(Cell::new(0.0));
+    let armed = Rc::new(Cell::new(false));
+    let pending: Rc<RefCell<Option<TimeoutHandle>>> = Rc::new(RefCell::new(None));
+    let check = {
+        let (touching, armed) = (touching.clone(), armed.clone());
+        Rc::new(move || {
+            if touching.get() || !armed.get() {
+                return;
+            }
+            if current_selection().is_some() {
+                on_select();
+            } else {
+                armed.set(false);
+            }
+        })
+    };
+    let schedule = {
+        let armed = armed.clone();
+        Rc::new(move || {
+            if !armed.get() {
+                return;
+            }
+            if let Some(handle) = pending.borrow_mut().take() {
+                handle.clear();
+            }
+            let check = check.clone();
+            *pending.borrow_mut() = set_timeout_with_handle(move || check(), SETTLE_DELAY).ok();
+        })
+    };
+
+    let on_start = {
+        let (touching, touch_start) = (touching.clone(), touch_start.clone());
+        Closure::wrap(Box::new(move |_: Event| {
+            touching.set(true);
+            touch_start.set(js_sys::Date::now());
+        }) as Box<dyn FnMut(_)>)
+    };
+    let on_end = {
+        let schedule = schedule.clone();
+        Closure::wrap(Box::new(move |_: Event| {
+            touching.set(false);
+            if js_sys::Date::now() - touch_start.get() >= LONG_PRESS.as_millis() as f64 {
+                armed.set(true);
+            }
+            schedule();
+        }) as Box<dyn FnMut(_)>)
+    };
+    let on_change = Closure::wrap(Box::new(move |_: Event| schedule()) as Box<dyn FnMut(_)>);
+    article.add_event_listener_with_callback("touchstart", on_start.as_ref().unchecked_ref()).ok();
+    for name in ["touchend", "touchcancel"] {
+        article.add_event_listener_with_callback(name, on_end.as_ref().unchecked_ref()).ok();
+    }
+    doc.add_event_listener_with_callback("selectionchange", on_change.as_ref().unchecked_ref()).ok();
+    on_start.forget();
+    on_end.forget();
+    on_change.forget();
+}
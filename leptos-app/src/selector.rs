@@ -0,0 +1,233 @@
+//! Web Annotation Data Model selectors and the `selectors` envelope stored
+//! with every annotation.
+//!
+//! Fields this client does not interpret are kept in `extra` maps so that a
+//! stored envelope written by another tool serializes back unchanged.
+use std::ops::Deref;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::offset::TextUnit;
+
+/// A single value or an array of them, as the model allows for `type`,
+/// `motivation`, `selector` and `state`. The original shape is preserved on serialization.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> Deref for OneOrMany<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match self {
+            OneOrMany::One(one) => std::slice::from_ref(one),
+            OneOrMany::Many(many) => many,
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for OneOrMany<T> {
+    fn from(items: Vec<T>) -> Self {
+        OneOrMany::Many(items)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum Selector {
+    #[serde(rename = "TextQuoteSelector")]
+    TextQuote {
+        exact: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        prefix: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        suffix: Option<String>,
+        #[serde(rename = "refinedBy", default, skip_serializing_if = "Option::is_none")]
+        refined_by: Option<Box<Selector>>,
+    },
+    #[serde(rename = "TextPositionSelector")]
+    TextPosition {
+        start: usize,
+        end: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unit: Option<TextUnit>,
+        #[serde(rename = "refinedBy", default, skip_serializing_if = "Option::is_none")]
+        refined_by: Option<Box<Selector>>,
+    },
+    #[serde(rename = "RangeSelector")]
+    Range {
+        #[serde(rename = "startSelector")]
+        start_selector: Box<Selector>,
+        #[serde(rename = "endSelector")]
+        end_selector: Box<Selector>,
+        #[serde(rename = "refinedBy", default, skip_serializing_if = "Option::is_none")]
+        refined_by: Option<Box<Selector>>,
+    },
+    #[serde(rename = "XPathSelector")]
+    XPath {
+        value: String,
+        #[serde(rename = "refinedBy", default, skip_serializing_if = "Option::is_none")]
+        refined_by: Option<Box<Selector>>,
+    },
+    #[serde(rename = "CssSelector")]
+    Css {
+        value: String,
+        #[serde(rename = "refinedBy", default, skip_serializing_if = "Option::is_none")]
+        refined_by: Option<Box<Selector>>,
+    },
+    #[serde(rename = "FragmentSelector")]
+    Fragment {
+        value: String,
+        #[serde(rename = "conformsTo", default, skip_serializing_if = "Option::is_none")]
+        conforms_to: Option<String>,
+        #[serde(rename = "refinedBy", default, skip_serializing_if = "Option::is_none")]
+        refined_by: Option<Box<Selector>>,
+    },
+    /// Any other selector, such as `SvgSelector`, kept as written. Only an
+    /// object qualifies, so a `selector` array is still read as many.
+    #[serde(untagged)]
+    Other(Map<String, Value>),
+}
+
+impl Selector {
+    pub fn refined_by(&self) -> Option<&Selector> {
+        match self {
+            Selector::TextQuote { refined_by, .. }
+            | Selector::TextPosition { refined_by, .. }
+            | Selector::Range { refined_by, .. }
+            | Selector::XPath { refined_by, .. }
+            | Selector::Css { refined_by, .. }
+            | Selector::Fragment { refined_by, .. } => refined_by.as_deref(),
+            Selector::Other(_) => None,
+        }
+    }
+}
+
+/// Resource states (`TimeState`, `HttpRequestState`) describing which
+/// version of the source the selectors apply to.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum State {
+    TimeState {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cached: Option<String>,
+        #[serde(rename = "sourceDate", default, skip_serializing_if = "Option::is_none")]
+        source_date: Option<String>,
+        #[serde(rename = "sourceDateStart", default, skip_serializing_if = "Option::is_none")]
+        source_date_start: Option<String>,
+        #[serde(rename = "sourceDateEnd", default, skip_serializing_if = "Option::is_none")]
+        source_date_end: Option<String>,
+        #[serde(rename = "refinedBy", default, skip_serializing_if = "Option::is_none")]
+        refined_by: Option<Box<State>>,
+    },
+    HttpRequestState {
+        value: String,
+        #[serde(rename = "refinedBy", default, skip_serializing_if = "Option::is_none")]
+        refined_by: Option<Box<State>>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Target {
+    pub source: String,
+    pub selector: OneOrMany<Selector>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<OneOrMany<State>>,
+    #[serde(rename = "styleClass", default, skip_serializing_if = "Option::is_none")]
+    pub style_class: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Envelope {
+    #[serde(rename = "@context", default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub r#type: OneOrMany<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub motivation: Option<OneOrMany<String>>,
+    pub target: Target,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Envelope {
+    pub fn new(source: String, selector: Vec<Selector>) -> Envelope {
+        Envelope {
+            context: None,
+            id: None,
+            r#type: OneOrMany::One("Annotation".into()),
+            motivation: None,
+            target: Target {
+                source,
+                selector: selector.into(),
+                state: None,
+                style_class: None,
+                scope: None,
+                extra: Map::new(),
+            },
+            extra: Map::new(),
+        }
+    }
+
+    /// Parse the `selectors` column as stored by the API.
+    pub fn from_json(raw: &str) -> Option<Envelope> {
+        serde_json::from_str(raw).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(raw: &str) {
+        let env: Envelope = serde_json::from_str(raw).expect("parse");
+        let expected: Value = serde_json::from_str(raw).unwrap();
+        assert_eq!(serde_json::to_value(&env).unwrap(), expected);
+    }
+
+    #[test]
+    fn stored_client_envelope_round_trips() {
+        round_trip(r#"{"type":"Annotation","target":{"source":"/posts/hello","selector":[
+            {"type":"TextPositionSelector","start":3,"end":9,"unit":"codepoint"},
+            {"type":"TextQuoteSelector","exact":"quoted","prefix":"ab","suffix":"cd"}]}}"#);
+    }
+
+    #[test]
+    fn full_model_round_trips() {
+        round_trip(r##"{"@context":"http://www.w3.org/ns/anno.jsonld","id":"urn:x","type":"Annotation",
+            "motivation":"commenting","creator":{"name":"someone"},
+            "target":{"source":"https://example.com/p","styleClass":"hl","scope":"https://example.com",
+              "state":{"type":"TimeState","cached":"https://archive/p","sourceDate":"2025-01-01T00:00:00Z"},
+              "selector":{"type":"RangeSelector",
+                "startSelector":{"type":"XPathSelector","value":"/p[1]"},
+                "endSelector":{"type":"CssSelector","value":"#s2",
+                  "refinedBy":{"type":"TextPositionSelector","start":0,"end":4}}},
+              "renderedVia":"tool"}}"##);
+        round_trip(r#"{"type":"Annotation","target":{"source":"/p","selector":[
+            {"type":"FragmentSelector","value":"para5","conformsTo":"http://tools.ietf.org/rfc/rfc3236",
+             "refinedBy":{"type":"TextQuoteSelector","exact":"x"}}],
+            "state":[{"type":"HttpRequestState","value":"Accept: text/html"}]}}"#);
+    }
+
+    #[test]
+    fn arrays_and_unknown_selectors_round_trip() {
+        let raw = r#"{"type":["Annotation","Note"],"motivation":["commenting","tagging"],
+            "target":{"source":"/p","selector":[
+              {"type":"SvgSelector","value":"<svg/>"},
+              {"type":"DataPositionSelector","start":4,"end":9},
+              {"type":"TextQuoteSelector","exact":"x"}],
+              "renderedVia":"tool"}}"#;
+        round_trip(raw);
+        let env = Envelope::from_json(raw).unwrap();
+        assert!(matches!(env.target.selector[2], Selector::TextQuote { .. }));
+    }
+}
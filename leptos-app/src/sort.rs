@@ -0,0 +1,117 @@
+//! Sort modes for the sidebar's threads, remembered in `localStorage`.
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use web_sys::{window, Storage};
+
+use crate::annotation::Annotation;
+use crate::selector::{Envelope, Selector};
+use crate::thread::Threads;
+
+const KEY: &str = "anno-sort";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortMode {
+    #[default]
+    Newest,
+    /// Document order of the highlighted passages.
+    ByText,
+    /// Most replies first, then the most recent reply.
+    MostActive,
+}
+
+impl SortMode {
+    pub const ALL: [SortMode; 3] = [SortMode::Newest, SortMode::ByText, SortMode::MostActive];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SortMode::Newest => "newest",
+            SortMode::ByText => "text",
+            SortMode::MostActive => "active",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortMode::Newest => "Newest",
+            SortMode::ByText => "By text",
+            SortMode::MostActive => "Most active",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<SortMode> {
+        SortMode::ALL.into_iter().find(|m| m.as_str() == s)
+    }
+
+    /// The reader's last choice.
+    pub fn load() -> SortMode {
+        storage()
+            .and_then(|s| s.get_item(KEY).ok().flatten())
+            .and_then(|raw| SortMode::parse(&raw))
+            .unwrap_or_default()
+    }
+
+    pub fn save(self) {
+        if let Some(store) = storage() {
+            let _ = store.set_item(KEY, self.as_str());
+        }
+    }
+}
+
+fn storage() -> Option<Storage> {
+    window()?.local_storage().ok().flatten()
+}
+
+/// Start of `a`'s passage: where it anchored in `positions`, or the stored
+/// `TextPosition` when it has not anchored.
+fn text_start(a: &Annotation, positions: &HashMap<i64, usize>) -> usize {
+    if let Some(start) = positions.get(&a.id) {
+        return *start;
+    }
+    let env = a.selectors.as_deref().and_then(Envelope::from_json);
+    env.and_then(|env| {
+        env.target.selector.iter().find_map(|s| match s {
+            Selector::TextPosition { start, .. } => Some(*start),
+            _ => None,
+        })
+    })
+    .unwrap_or(usize::MAX)
+}
+
+/// Order `roots` for `mode`; ties fall back to the newest first.
+pub fn sort_roots(roots: &mut [&Annotation], mode: SortMode, threads: &Threads, positions: &HashMap<i64, usize>) {
+    roots.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    match mode {
+        SortMode::Newest => {}
+        SortMode::ByText => roots.sort_by_key(|a| text_start(a, positions)),
+        SortMode::MostActive => {
+            roots.sort_by_key(|a| Reverse((threads.reply_count(a.id), threads.latest_reply(a.id).map(str::to_string))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotation::fixture as anno;
+
+    #[test]
+    fn sorts_roots_by_mode() {
+        let items = vec![
+            anno(1, None, "2024-05-01 10:00:00"),
+            anno(2, None, "2024-05-01 11:00:00"),
+            anno(3, None, "2024-05-01 12:00:00"),
+            anno(4, Some(1), "2024-05-01 13:00:00"),
+            anno(5, Some(2), "2024-05-01 14:00:00"),
+        ];
+        let threads = Threads::new(&items);
+        let ids = |mode, positions: &HashMap<i64, usize>| {
+            let mut roots: Vec<&Annotation> = items.iter().filter(|a| a.parent_id.is_none()).collect();
+            sort_roots(&mut roots, mode, &threads, positions);
+            roots.iter().map(|a| a.id).collect::<Vec<_>>()
+        };
+        assert_eq!(ids(SortMode::Newest, &HashMap::new()), vec![3, 2, 1]);
+        assert_eq!(ids(SortMode::ByText, &HashMap::from([(1, 40), (2, 5), (3, 12)])), vec![2, 3, 1]);
+        assert_eq!(ids(SortMode::MostActive, &HashMap::new()), vec![2, 1, 3]);
+    }
+}
@@ -0,0 +1,172 @@
+//! Client-side annotation store.
+//!
+//! Server pages are merged in by id, and the reader's own annotations are
+//! inserted before the request completes under a negative provisional id,
+//! then either reconciled with the id the server assigns or discarded.
+use leptos::*;
+
+use crate::annotation::{Annotation, Sync};
+
+#[derive(Clone, Copy)]
+pub struct AnnotationStore {
+    items: RwSignal<Vec<Annotation>>,
+    next_local: StoredValue<i64>,
+}
+
+impl AnnotationStore {
+    pub fn new() -> AnnotationStore {
+        AnnotationStore { items: create_rw_signal(vec![]), next_local: store_value(-1) }
+    }
+
+    pub fn with<R>(&self, f: impl FnOnce(&Vec<Annotation>) -> R) -> R {
+        self.items.with(f)
+    }
+
+    pub fn get_untracked(&self, id: i64) -> Option<Annotation> {
+        self.items.with_untracked(|items| items.iter().find(|a| a.id == id).cloned())
+    }
+
+    /// Add or refresh rows fetched from the server. Rows already held as
+    /// fetched, as every live poll re-fetches some, do not notify anyone.
+    pub fn merge(&self, fetched: Vec<Annotation>) {
+        let changed = self.items.with_untracked(|items| changes_anything(items, &fetched));
+        if changed {
+            self.items.update(|items| merge_into(items, fetched));
+        }
+    }
+
+    /// Insert `a` as [`Sync::Sending`] and return its provisional id.
+    pub fn insert_provisional(&self, a: Annotation) -> i64 {
+        self.insert_provisional_with(a, |_| {})
+    }
+
+    /// Like [`insert_provisional`](Self::insert_provisional), calling
+    /// `before_insert` with the id first. Effects run as soon as the
+    /// entry is added, so this is where e.g. existing marks are claimed.
+    pub fn insert_provisional_with(&self, mut a: Annotation, before_insert: impl FnOnce(i64)) -> i64 {
+        let id = self.next_local.get_value();
+        self.next_local.set_value(id - 1);
+        before_insert(id);
+        a.id = id;
+        a.sync = Sync::Sending;
+        self.items.update(|items| items.push(a));
+        id
+    }
+
+    /// Give provisional `local_id` the id and state the server assigned.
+    pub fn confirm(&self, local_id: i64, id: i64, state: Option<String>) {
+        self.items.update(|items| reconcile(items, local_id, id, state));
+    }
+
+    pub fn set_sync(&self, id: i64, sync: Sync) {
+        self.items.update(|items| {
+            if let Some(a) = items.iter_mut().find(|a| a.id == id) {
+                a.sync = sync;
+            }
+        });
+    }
+
+    pub fn discard(&self, local_id: i64) {
+        self.items.update(|items| items.retain(|a| a.id != local_id));
+    }
+}
+
+/// Provisional ids are negative so they can never clash with server rows.
+pub fn is_provisional(id: i64) -> bool {
+    id < 0
+}
+
+fn changes_anything(items: &[Annotation], fetched: &[Annotation]) -> bool {
+    fetched.iter().any(|a| !items.contains(a))
+}
+
+fn merge_into(items: &mut Vec<Annotation>, fetched: Vec<Annotation>) {
+    for a in fetched {
+        match items.iter_mut().find(|b| b.id == a.id) {
+            Some(existing) => *existing = a,
+            None => items.push(a),
+        }
+    }
+}
+
+fn reconcile(items: &mut Vec<Annotation>, local_id: i64, id: i64, state: Option<String>) {
+    // a fetch may already have brought the saved row in
+    if items.iter().any(|a| a.id == id) {
+        items.retain(|a| a.id != local_id);
+        return;
+    }
+    if let Some(a) = items.iter_mut().find(|a| a.id == local_id) {
+        a.id = id;
+        a.state = state;
+        a.sync = Sync::Saved;
+        for reply in items.iter_mut().filter(|r| r.parent_id == Some(local_id)) {
+            reply.parent_id = Some(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotation::fixture;
+
+    fn anno(id: i64, body: &str) -> Annotation {
+        Annotation { body_html: body.into(), sync: Sync::Sending, ..fixture(id, None, "2024-05-01 10:00:00") }
+    }
+
+    #[test]
+    fn reconciles_provisional_entries() {
+        let mut items = vec![anno(1, "a"), anno(-1, "mine")];
+        reconcile(&mut items, -1, 7, Some("pending".into()));
+        assert_eq!(items[1].id, 7);
+        assert_eq!(items[1].sync, Sync::Saved);
+        assert!(items[1].is_held());
+
+        let mut items = vec![anno(-2, "mine")];
+        merge_into(&mut items, vec![anno(8, "mine"), anno(1, "a")]);
+        reconcile(&mut items, -2, 8, None);
+        assert_eq!(items.iter().map(|a| a.id).collect::<Vec<_>>(), vec![8, 1]);
+    }
+
+    #[test]
+    fn merging_known_rows_changes_nothing() {
+        let runtime = create_runtime();
+        let store = AnnotationStore::new();
+        let runs = store_value(0);
+        create_effect(move |_| store.with(|_| runs.update_value(|n| *n += 1)));
+        let saved = |id| Annotation { sync: Sync::Saved, ..anno(id, "a") };
+        store.merge(vec![saved(1)]);
+        store.merge(vec![saved(1)]);
+        assert_eq!(runs.get_value(), 2);
+        store.merge(vec![saved(1), saved(2)]);
+        assert_eq!(runs.get_value(), 3);
+        runtime.dispose();
+    }
+
+    #[test]
+    fn claimed_provisional_entries_are_marked_once() {
+        use std::collections::HashMap;
+
+        use crate::anchoring::Anchored;
+
+        let runtime = create_runtime();
+        let store = AnnotationStore::new();
+        let anchored = store_value(Anchored::default());
+        let marks = store_value(HashMap::<i64, usize>::new());
+        let mark = move |a: &Annotation| {
+            marks.update_value(|m| *m.entry(a.id).or_default() += 1);
+            Ok(0)
+        };
+        // the sidebar's anchoring effect
+        create_effect(move |_| store.with(|items| anchored.update_value(|state| { state.pass(items, mark); })));
+        store.merge(vec![anno(1, "a")]);
+        // the composer's own-draft marks are relabelled to the new id
+        let id = store.insert_provisional_with(anno(0, "mine"), |id| {
+            anchored.update_value(|state| state.claim(id));
+            mark(&anno(id, "mine")).unwrap();
+        });
+        store.merge(vec![anno(2, "b")]);
+        assert_eq!(marks.with_value(|m| (m.get(&1).copied(), m.get(&id).copied())), (Some(1), Some(1)));
+        runtime.dispose();
+    }
+}
@@ -0,0 +1,226 @@
+//! Recursive rendering of annotation threads in the sidebar.
+use std::collections::{HashMap, HashSet};
+
+use leptos::*;
+
+use crate::annotation::{Annotation, Sync};
+use crate::fragment;
+use crate::sanitize;
+use crate::search::Query;
+
+pub fn card_dom_id(id: i64) -> String {
+    format!("anno-{}", id)
+}
+
+/// Identity of a rendered card. Cards are kept while their row's sync and
+/// moderation state stay the same, so an open reply composer survives
+/// other rows arriving.
+pub fn card_key(a: &Annotation) -> (i64, Sync, Option<String>) {
+    (a.id, a.sync, a.state.clone())
+}
+
+/// Replies grouped under their parent, oldest first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Threads {
+    children: HashMap<i64, Vec<Annotation>>,
+}
+
+impl Threads {
+    pub fn new(items: &[Annotation]) -> Threads {
+        let mut children: HashMap<i64, Vec<Annotation>> = HashMap::new();
+        for a in items {
+            if let Some(parent) = a.parent_id {
+                children.entry(parent).or_default().push(a.clone());
+            }
+        }
+        for replies in children.values_mut() {
+            replies.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        }
+        Threads { children }
+    }
+
+    pub fn replies(&self, id: i64) -> &[Annotation] {
+        self.children.get(&id).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Replies at any depth below `id`.
+    pub fn reply_count(&self, id: i64) -> usize {
+        self.replies(id).iter().map(|r| 1 + self.reply_count(r.id)).sum()
+    }
+
+    /// Ids of every reply below `id`, in the order they are shown.
+    pub fn descendants(&self, id: i64) -> Vec<i64> {
+        self.replies(id).iter().flat_map(|r| std::iter::once(r.id).chain(self.descendants(r.id))).collect()
+    }
+
+    /// `created_at` of the newest reply at any depth below `id`.
+    pub fn latest_reply(&self, id: i64) -> Option<&str> {
+        self.replies(id)
+            .iter()
+            .flat_map(|r| [r.created_at.as_deref(), self.latest_reply(r.id)])
+            .flatten()
+            .max()
+    }
+}
+
+/// Signals and callbacks shared by every card in the sidebar.
+#[derive(Clone, Copy)]
+pub struct ThreadCtx {
+    /// Replies of every loaded annotation; cards update their own replies.
+    pub threads: Memo<Threads>,
+    pub focused: ReadSignal<Option<i64>>,
+    pub set_focused: WriteSignal<Option<i64>>,
+    pub collapsed: RwSignal<HashSet<i64>>,
+    pub reply_to: RwSignal<Option<i64>>,
+    pub reply_body: RwSignal<String>,
+    pub reply_status: RwSignal<String>,
+    pub on_send_reply: Callback<i64>,
+    pub on_reveal: Callback<i64>,
+    pub on_copy_link: Callback<i64>,
+    /// Header search; matches are marked in quotes, authors and bodies.
+    pub query: Memo<Query>,
+    /// Annotations this reader reported; their cards are dimmed.
+    pub reported: RwSignal<HashSet<i64>>,
+    pub on_report: Callback<i64>,
+    /// Send a parked annotation again, or drop it.
+    pub on_retry_send: Callback<i64>,
+    pub on_discard: Callback<i64>,
+}
+
+pub fn render_thread(root: &Annotation, ctx: ThreadCtx) -> View {
+    render_node(root, 0, ctx)
+}
+
+/// The replies below `id` alone, for roots shown outside a thread such as
+/// orphans.
+pub fn render_replies(id: i64, ctx: ThreadCtx) -> View {
+    replies_view(id, 0, ctx).into_view()
+}
+
+// Replies to the card at `depth`, hidden while its thread is collapsed.
+fn replies_view(id: i64, depth: usize, ctx: ThreadCtx) -> impl IntoView {
+    let has_replies = create_memo(move |_| ctx.threads.with(|t| !t.replies(id).is_empty()));
+    move || {
+        if ctx.collapsed.with(|c| c.contains(&id)) || !has_replies.get() {
+            return None;
+        }
+        Some(view! {
+          <div class="thread-children">
+            <For each=move || ctx.threads.with(|t| t.replies(id).to_vec()) key=card_key
+              children=move |r| render_node(&r, depth + 1, ctx) />
+          </div>
+        })
+    }
+}
+
+fn render_node(a: &Annotation, depth: usize, ctx: ThreadCtx) -> View {
+    let id = a.id;
+    let count = create_memo(move |_| ctx.threads.with(|t| t.reply_count(id)));
+    let base_class = match (depth == 0, a.sync) {
+        (true, Sync::Saved) => "item root",
+        (true, _) => "item root pending",
+        (false, Sync::Saved) => "item reply",
+        (false, _) => "item reply pending",
+    };
+    let sync_label = match a.sync {
+        Sync::Sending => Some("Sending…"),
+        Sync::Queued => Some("Pending sync"),
+        Sync::Failed => Some("Not sent"),
+        Sync::Saved if a.is_held() => Some("Awaiting moderation"),
+        Sync::Saved => None,
+    };
+    // the server needs a real id to reply to
+    let can_reply = a.sync == Sync::Saved;
+    let quote_text = a.quote.clone().unwrap_or_default();
+    let quote = (depth == 0).then(|| view! {
+        <button class="anno-quote anno-quote--link" title="Show in article" on:click=move |_| ctx.on_reveal.call(id)>
+          {"“"}{move || ctx.query.with(|q| q.render(&quote_text))}{"”"}
+        </button>
+    });
+    let author = a.author();
+    let body = sanitize::parse(&a.body_html);
+    let toggle = move || (count.get() > 0).then(|| view! {
+        <button class="btn-link thread-toggle"
+          attr:aria-expanded=move || (!ctx.collapsed.with(|c| c.contains(&id))).to_string()
+          on:click=move |_| ctx.collapsed.update(|c| if !c.remove(&id) { c.insert(id); })>
+          {move || {
+              let arrow = if ctx.collapsed.with(|c| c.contains(&id)) { "▸" } else { "▾" };
+              let count = count.get();
+              format!("{} {} {}", arrow, count, if count == 1 { "reply" } else { "replies" })
+          }}
+        </button>
+    });
+    let children = replies_view(id, depth, ctx);
+    view! {
+      <div id=card_dom_id(id)
+        class=move || {
+            let mut class = base_class.to_string();
+            if ctx.focused.get() == Some(id) { class.push_str(" is-focused"); }
+            if ctx.reported.with(|r| r.contains(&id)) { class.push_str(" is-reported"); }
+            class
+        }
+        on:mouseenter=move |_| if depth == 0 { ctx.set_focused.set(Some(id)) }>
+        {quote}
+        <div class="meta">
+          <span class=a.kind.badge_class()>{a.kind.label()}</span>
+          {move || ctx.query.with(|q| q.render(&author))}
+          <a class="permalink" href=fragment::annotation_hash(id) title="Copy link to this annotation" on:click=move |e| {
+            e.prevent_default();
+            ctx.on_copy_link.call(id);
+          }>{"#"}</a>
+          {sync_label.map(|label| view! { <span class="sync-label">{label}</span> })}
+          {move || ctx.reported.with(|r| r.contains(&id)).then(|| view! { <span class="sync-label">{"Reported"}</span> })}
+        </div>
+        <div class="anno-body">{move || ctx.query.with(|q| sanitize::render_nodes_with(&body, &|t| q.render(t)))}</div>
+        <div class="card-actions">
+          {can_reply.then(|| view! {
+            <button class="btn-link" on:click=move |_| {
+              ctx.reply_status.set(String::new());
+              ctx.reply_to.set(Some(id));
+            }>Reply</button>
+          })}
+          {move || (can_reply && !ctx.reported.with(|r| r.contains(&id))).then(|| view! {
+            <button class="btn-link btn-report" aria-haspopup="dialog" on:click=move |_| ctx.on_report.call(id)>Report</button>
+          })}
+          {(a.sync == Sync::Failed).then(|| view! {
+            <button class="btn-link" on:click=move |_| ctx.on_retry_send.call(id)>Retry</button>
+            <button class="btn-link" on:click=move |_| ctx.on_discard.call(id)>Discard</button>
+          })}
+          {toggle}
+        </div>
+        {move || (ctx.reply_to.get() == Some(id)).then(|| view! {
+          <div class="reply-composer">
+            <textarea class="btn" placeholder="返信を入力" aria-label="Reply"
+              prop:value=ctx.reply_body.get_untracked()
+              on:input=move |e| ctx.reply_body.set(event_target_value(&e))></textarea>
+            <div style="display:flex;gap:.5rem">
+              <button class="btn btn-primary" on:click=move |_| ctx.on_send_reply.call(id)>返信</button>
+              <button class="btn" on:click=move |_| ctx.reply_to.set(None)>キャンセル</button>
+            </div>
+            <div class="status">{move || ctx.reply_status.get()}</div>
+          </div>
+        })}
+        {children}
+      </div>
+    }.into_view()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotation::fixture as anno;
+
+    #[test]
+    fn counts_nested_replies_and_orders_them() {
+        let items = [
+            anno(1, None, "1"),
+            anno(3, Some(1), "3"),
+            anno(2, Some(1), "2"),
+            anno(4, Some(2), "4"),
+        ];
+        let threads = Threads::new(&items);
+        assert_eq!(threads.reply_count(1), 3);
+        assert_eq!(threads.reply_count(2), 1);
+        assert_eq!(threads.replies(1).iter().map(|a| a.id).collect::<Vec<_>>(), vec![2, 3]);
+    }
+}
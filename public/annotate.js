@@ -105,7 +105,8 @@
       target: {
         source: location.pathname,
         selector: [
-          { type: 'TextPositionSelector', start: startPos, end: endPos, unit: 'codepoint' },
+          // DOM text offsets are UTF-16 code units
+          { type: 'TextPositionSelector', start: startPos, end: endPos, unit: 'utf16' },
           { type: 'TextQuoteSelector', exact, prefix, suffix }
         ]
       }
@@ -9,7 +9,7 @@ export type TextPositionSelector = {
   type: 'TextPositionSelector';
   start: number;
   end: number;
-  unit: 'codepoint';
+  unit: 'codepoint' | 'utf16' | 'byte';
 };
 
 export type AnnotationTarget = {
@@ -68,7 +68,7 @@ export function createModRouter(db: Database) {
           target: {
             source: `/posts/${slug}`,
             selector: [
-              { type: 'TextPositionSelector', start, end, unit: 'codepoint' },
+              { type: 'TextPositionSelector', start, end, unit: 'utf16' },
               { type: 'TextQuoteSelector', exact: q, prefix: plain.slice(Math.max(0, start - 30), start), suffix: plain.slice(end, Math.min(plain.length, end + 30)) }
             ]
           }